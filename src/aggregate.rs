@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use crate::exchange::{Exchange, PriceUpdate};
+
+/// One price for a pair monitored on several exchanges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregatedPrice {
+    pub pair: String,
+    /// Average of the exchanges' latest prices, weighted by their 24h volume.
+    pub price: Decimal,
+    /// Exchanges that contributed, in no particular order.
+    pub exchanges: Vec<Exchange>,
+}
+
+/// Keeps the latest update of every pair on every exchange and consolidates
+/// the exchanges of each pair. The per-exchange updates are left untouched.
+#[derive(Default)]
+pub struct Aggregator {
+    latest: HashMap<String, HashMap<Exchange, PriceUpdate>>,
+}
+
+impl Aggregator {
+    /// Record `update` and return the pair's new consolidated price, if more
+    /// than one exchange has reported it.
+    pub fn push(&mut self, update: &PriceUpdate) -> Option<AggregatedPrice> {
+        self.latest
+            .entry(update.pair.clone())
+            .or_default()
+            .insert(update.exchange, update.clone());
+        self.get(&update.pair)
+    }
+
+    pub fn get(&self, pair: &str) -> Option<AggregatedPrice> {
+        let updates: Vec<&PriceUpdate> = self.latest.get(pair)?.values().collect();
+        if updates.len() < 2 {
+            return None;
+        }
+        Some(AggregatedPrice {
+            pair: pair.to_string(),
+            price: volume_weighted(&updates)?,
+            exchanges: updates.iter().map(|update| update.exchange).collect(),
+        })
+    }
+
+    /// Forget a pair on one exchange, e.g. once it stopped.
+    pub fn remove(&mut self, exchange: Exchange, pair: &str) {
+        if let Some(exchanges) = self.latest.get_mut(pair) {
+            exchanges.remove(&exchange);
+        }
+    }
+
+    /// Forget the pairs not in `pairs`.
+    pub fn retain(&mut self, pairs: &[(Exchange, String)]) {
+        for (pair, exchanges) in &mut self.latest {
+            exchanges.retain(|exchange, _| pairs.contains(&(*exchange, pair.clone())));
+        }
+        self.latest.retain(|_, exchanges| !exchanges.is_empty());
+    }
+}
+
+/// The prices' average weighted by 24h volume. Updates without a volume are
+/// left out; if none has one, every price counts the same.
+pub fn volume_weighted(updates: &[&PriceUpdate]) -> Option<Decimal> {
+    let weighted: Vec<(Decimal, Decimal)> = updates
+        .iter()
+        .filter_map(|update| Some((update.price, update.volume_24h?)))
+        .filter(|(_, volume)| volume.is_sign_positive() && !volume.is_zero())
+        .collect();
+    if weighted.is_empty() {
+        if updates.is_empty() {
+            return None;
+        }
+        let sum: Decimal = updates.iter().map(|update| update.price).sum();
+        return Some(sum / Decimal::from(updates.len()));
+    }
+    let total: Decimal = weighted.iter().map(|(_, volume)| volume).sum();
+    let sum: Decimal = weighted.iter().map(|(price, volume)| price * volume).sum();
+    Some(sum / total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(exchange: Exchange, price: &str, volume: Option<&str>) -> PriceUpdate {
+        PriceUpdate {
+            volume_24h: volume.map(|volume| volume.parse().unwrap()),
+            ..PriceUpdate::new(exchange, "BTC-USDT", price.parse().unwrap())
+        }
+    }
+
+    fn dec(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn weights_prices_by_volume() {
+        let okx = update(Exchange::Okx, "100", Some("1"));
+        let binance = update(Exchange::Binance, "200", Some("3"));
+        assert_eq!(volume_weighted(&[&okx, &binance]), Some(dec("175")));
+    }
+
+    #[test]
+    fn averages_evenly_without_volumes() {
+        let okx = update(Exchange::Okx, "100", None);
+        let binance = update(Exchange::Binance, "200", None);
+        assert_eq!(volume_weighted(&[&okx, &binance]), Some(dec("150")));
+        assert_eq!(volume_weighted(&[]), None);
+    }
+
+    #[test]
+    fn leaves_out_zero_negative_and_missing_volumes() {
+        let okx = update(Exchange::Okx, "100", Some("2"));
+        for volume in [Some("0"), Some("-5"), None] {
+            let binance = update(Exchange::Binance, "200", volume);
+            assert_eq!(volume_weighted(&[&okx, &binance]), Some(dec("100")));
+        }
+        // With no usable volume at all, every price counts the same.
+        let okx = update(Exchange::Okx, "100", Some("0"));
+        let binance = update(Exchange::Binance, "200", Some("-1"));
+        assert_eq!(volume_weighted(&[&okx, &binance]), Some(dec("150")));
+    }
+
+    #[test]
+    fn needs_more_than_one_exchange() {
+        let mut aggregator = Aggregator::default();
+        assert_eq!(
+            aggregator.push(&update(Exchange::Okx, "100", Some("1"))),
+            None
+        );
+        assert_eq!(
+            aggregator.push(&update(Exchange::Okx, "110", Some("1"))),
+            None
+        );
+
+        let aggregated = aggregator
+            .push(&update(Exchange::Binance, "130", Some("1")))
+            .unwrap();
+        assert_eq!(aggregated.price, dec("120"));
+        assert_eq!(aggregated.exchanges.len(), 2);
+
+        aggregator.remove(Exchange::Binance, "BTC-USDT");
+        assert_eq!(aggregator.get("BTC-USDT"), None);
+    }
+
+    #[test]
+    fn retain_forgets_dropped_pairs() {
+        let mut aggregator = Aggregator::default();
+        aggregator.push(&update(Exchange::Okx, "100", None));
+        aggregator.push(&update(Exchange::Binance, "200", None));
+        aggregator.retain(&[(Exchange::Okx, "BTC-USDT".to_string())]);
+        assert_eq!(aggregator.get("BTC-USDT"), None);
+        aggregator.retain(&[]);
+        assert!(aggregator.latest.is_empty());
+    }
+}
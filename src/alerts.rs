@@ -0,0 +1,194 @@
+use rust_decimal::Decimal;
+
+use crate::config::AlertRule;
+use crate::exchange::{self, Exchange, PriceUpdate};
+use crate::sink::PriceSink;
+
+/// A threshold that fires once when the price crosses it and re-arms only
+/// after the price has moved back to the other side.
+struct Threshold {
+    price: Decimal,
+    above: bool,
+    /// `None` until the first price is seen, so a price that is already past
+    /// the threshold at startup does not fire.
+    armed: Option<bool>,
+}
+
+impl Threshold {
+    fn new(price: Decimal, above: bool) -> Self {
+        Self {
+            price,
+            above,
+            armed: None,
+        }
+    }
+
+    fn is_crossed(&self, price: Decimal) -> bool {
+        if self.above {
+            price >= self.price
+        } else {
+            price <= self.price
+        }
+    }
+
+    /// Returns true if this update crossed the threshold.
+    fn check(&mut self, price: Decimal) -> bool {
+        let crossed = self.is_crossed(price);
+        let fire = crossed && self.armed == Some(true);
+        self.armed = Some(!crossed);
+        fire
+    }
+}
+
+struct Rule {
+    key: (Exchange, String),
+    thresholds: Vec<Threshold>,
+}
+
+/// Edge-triggered price alerts for the configured [`AlertRule`]s.
+pub struct Alerts {
+    rules: Vec<Rule>,
+}
+
+impl Alerts {
+    pub fn new(rules: &[AlertRule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter_map(|rule| {
+                let key = exchange::parse_pair(&rule.pair).ok()?;
+                let thresholds = rule
+                    .above
+                    .map(|price| Threshold::new(price, true))
+                    .into_iter()
+                    .chain(rule.below.map(|price| Threshold::new(price, false)))
+                    .collect();
+                Some(Rule { key, thresholds })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Feed an update through the rules and return a message for every
+    /// threshold it crossed.
+    pub fn check(&mut self, update: &PriceUpdate) -> Vec<String> {
+        let mut messages = Vec::new();
+        for rule in &mut self.rules {
+            if rule.key.0 != update.exchange || rule.key.1 != update.pair {
+                continue;
+            }
+            for threshold in &mut rule.thresholds {
+                if threshold.check(update.price) {
+                    let direction = if threshold.above { "above" } else { "below" };
+                    messages.push(format!(
+                        "{} is {direction} {} at {}",
+                        update.pair, threshold.price, update.price
+                    ));
+                }
+            }
+        }
+        messages
+    }
+}
+
+/// Notifies of the alerts on its own, for running without the tray, which
+/// otherwise checks them as it shows the prices.
+impl PriceSink for Alerts {
+    fn handle(&mut self, update: &PriceUpdate) {
+        for message in self.check(update) {
+            notify(&message);
+        }
+    }
+}
+
+/// Show a desktop notification, logging instead of failing if the platform
+/// has no notification service.
+pub fn notify(message: &str) {
+    tracing::info!("alert: {message}");
+    if let Err(err) = notify_rust::Notification::new()
+        .summary("Price alert")
+        .body(message)
+        .show()
+    {
+        tracing::warn!("failed to show notification: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alerts(pair: &str, above: Option<&str>, below: Option<&str>) -> Alerts {
+        Alerts::new(&[AlertRule {
+            pair: pair.to_string(),
+            above: above.map(|price| price.parse().unwrap()),
+            below: below.map(|price| price.parse().unwrap()),
+        }])
+    }
+
+    /// Feed `prices` for OKX BTC-USDT, returning what each one fired.
+    fn feed(alerts: &mut Alerts, prices: &[&str]) -> Vec<Vec<String>> {
+        prices
+            .iter()
+            .map(|price| {
+                let update = PriceUpdate::new(Exchange::Okx, "BTC-USDT", price.parse().unwrap());
+                alerts.check(&update)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn does_not_fire_when_already_past_at_startup() {
+        let mut alerts = alerts("BTC-USDT", Some("100"), None);
+        let fired = feed(&mut alerts, &["105", "106", "99", "101"]);
+        assert_eq!(fired.iter().map(Vec::len).collect::<Vec<_>>(), [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn fires_once_per_crossing() {
+        let mut alerts = alerts("BTC-USDT", Some("100"), None);
+        let fired = feed(&mut alerts, &["99", "101", "102", "103"]);
+        assert_eq!(fired[1], ["BTC-USDT is above 100 at 101"]);
+        assert!(fired[2..].iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn re_arms_only_after_moving_back() {
+        let mut alerts = alerts("BTC-USDT", None, Some("100"));
+        // Touching the threshold counts as crossing it, so 100 doesn't re-arm.
+        let fired = feed(&mut alerts, &["101", "99", "100", "98", "101", "100"]);
+        assert_eq!(
+            fired.iter().map(Vec::len).collect::<Vec<_>>(),
+            [0, 1, 0, 0, 0, 1]
+        );
+        assert_eq!(fired[5], ["BTC-USDT is below 100 at 100"]);
+    }
+
+    #[test]
+    fn above_and_below_on_the_same_rule_are_separate() {
+        let mut alerts = alerts("BTC-USDT", Some("110"), Some("90"));
+        let fired = feed(&mut alerts, &["100", "111", "89", "100", "111"]);
+        assert_eq!(
+            fired,
+            [
+                vec![],
+                vec!["BTC-USDT is above 110 at 111".to_string()],
+                vec!["BTC-USDT is below 90 at 89".to_string()],
+                vec![],
+                vec!["BTC-USDT is above 110 at 111".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_updates_for_other_pairs() {
+        let mut alerts = alerts("okx:BTC-USDT", Some("100"), None);
+        for (exchange, pair) in [(Exchange::Okx, "ETH-USDT"), (Exchange::Binance, "BTC-USDT")] {
+            for price in ["99", "101"] {
+                let update = PriceUpdate::new(exchange, pair, price.parse().unwrap());
+                assert!(alerts.check(&update).is_empty());
+            }
+        }
+        // Nor did they arm the rule: its pair's first price never fires.
+        assert!(feed(&mut alerts, &["101"])[0].is_empty());
+    }
+}
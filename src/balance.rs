@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use exc_okx::key::OkxKey;
+use rust_decimal::Decimal;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::config::Config;
+use crate::exchange;
+
+const BALANCE_URL: &str = "https://www.okx.com";
+/// OKX reports the account's total equity in dollars.
+const CURRENCY: &str = "USD";
+const REFRESH: Duration = Duration::from_secs(30);
+/// Wait before trying again after a failed fetch.
+const RETRY: Duration = Duration::from_secs(60);
+
+/// The account balance at some point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceUpdate {
+    pub currency: String,
+    /// Total equity across the account's currencies.
+    pub total: Decimal,
+    /// When the balance was received, in milliseconds since the epoch.
+    pub timestamp_ms: i64,
+}
+
+/// The OKX account balance, refreshed in the background with the API key
+/// from the environment. exc only streams public tickers, so this polls the
+/// signed REST endpoint. The refresh stops when this is dropped.
+pub struct Balance {
+    balance: watch::Receiver<Option<BalanceUpdate>>,
+    task: JoinHandle<()>,
+}
+
+impl Balance {
+    /// Start fetching the balance if `show_balance` is set and there is a key.
+    pub fn spawn(config: &Config) -> Option<Self> {
+        if !config.show_balance {
+            return None;
+        }
+        let Some(credentials) = &config.okx_credentials else {
+            tracing::warn!(
+                "not showing the balance: set TICKER_OKX_API_KEY, TICKER_OKX_API_SECRET \
+                 and TICKER_OKX_API_PASSPHRASE"
+            );
+            return None;
+        };
+        let key = OkxKey::new(
+            &credentials.key,
+            &credentials.secret,
+            &credentials.passphrase,
+        );
+        let http = exchange::http_client(config)
+            .map_err(|err| tracing::warn!("not showing the balance: {err}"))
+            .ok()?;
+        let (tx, balance) = watch::channel(None);
+        let task = tokio::spawn(async move {
+            loop {
+                let wait = match fetch(&http, &key).await {
+                    Ok(update) => {
+                        tracing::debug!("balance: {} {}", update.total, update.currency);
+                        if tx.send(Some(update)).is_err() {
+                            break;
+                        }
+                        REFRESH
+                    }
+                    Err(err) => {
+                        tracing::warn!("failed to fetch the balance: {err}");
+                        RETRY
+                    }
+                };
+                tokio::time::sleep(wait).await;
+            }
+        });
+        Some(Self { balance, task })
+    }
+
+    /// The latest balance, if one was fetched yet.
+    pub fn latest(&self) -> Option<BalanceUpdate> {
+        self.balance.borrow().clone()
+    }
+
+    /// Whether a new balance came in since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.balance.has_changed().unwrap_or(false);
+        self.balance.borrow_and_update();
+        changed
+    }
+}
+
+impl Drop for Balance {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The account's total equity, from `GET /api/v5/account/balance`:
+/// `{"code": "0", "data": [{"totalEq": "1234.5"}]}`, in US dollars.
+async fn fetch(http: &reqwest::Client, key: &OkxKey) -> anyhow::Result<BalanceUpdate> {
+    let path = "/api/v5/account/balance";
+    let signature = key.sign_now("GET", path, false)?;
+    let body: serde_json::Value = http
+        .get(format!("{BALANCE_URL}{path}"))
+        .header("OK-ACCESS-KEY", key.apikey.as_str())
+        .header("OK-ACCESS-SIGN", signature.signature.as_str())
+        .header("OK-ACCESS-TIMESTAMP", signature.timestamp.as_str())
+        .header("OK-ACCESS-PASSPHRASE", key.passphrase.as_str())
+        .send()
+        .await?
+        .json()
+        .await?;
+    if body["code"].as_str() != Some("0") {
+        anyhow::bail!(
+            "OKX answered {}: {}",
+            body["code"],
+            body["msg"].as_str().unwrap_or_default()
+        );
+    }
+    let total = body["data"][0]["totalEq"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("no totalEq in the response"))?
+        .parse()?;
+    Ok(BalanceUpdate {
+        currency: CURRENCY.to_string(),
+        total,
+        timestamp_ms: chrono::Utc::now().timestamp_millis(),
+    })
+}
@@ -0,0 +1,1948 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+#[cfg(feature = "tray")]
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+#[cfg(feature = "tray")]
+use notify::{RecursiveMode, Watcher};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, TickerError};
+use crate::exchange::{self, Exchange};
+use crate::number_format::{NumberLocale, RoundingMode};
+use crate::relay;
+
+/// How long the config file has to stay untouched before it is reloaded, so
+/// that an editor saving in several steps triggers a single reload.
+#[cfg(feature = "tray")]
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+/// Sent as the `User-Agent` of REST requests without a `user_agent`.
+const DEFAULT_USER_AGENT: &str = concat!("crypto-coin-ticker/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Pairs to monitor as `BASE-QUOTE`, optionally prefixed with the exchange,
+    /// e.g. `["BTC-USDT", "binance:ETH-USDT"]`. Unprefixed pairs use OKX.
+    /// An entry can also be a table with per-pair overrides, see [`PairConfig`].
+    pub trading_pairs: Vec<PairConfig>,
+    /// Minimum number of seconds between two price updates of a pair.
+    pub update_interval_secs: u64,
+    /// Decimal places shown for prices, at most as many as the pair's tick
+    /// size has. Unset uses the tick size's. A pair's own `decimals` isn't
+    /// capped. Tiny prices get more, so that a few significant figures remain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decimals: Option<u32>,
+    /// Ignore prices that moved less than this percentage from the one shown,
+    /// e.g. `0.05`. Can be set per pair.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_change_pct: Option<Decimal>,
+    /// Show a price after this long even if it didn't move `min_change_pct`.
+    pub max_unchanged_secs: u64,
+    /// Flash the tray icon for a second when a pair moves at least this
+    /// percentage in one update, e.g. `1`. Once per move, not while it goes on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flash_threshold_pct: Option<Decimal>,
+    /// What the percentage change in the tooltip is measured against.
+    pub change_baseline: ChangeBaseline,
+    /// Digit grouping and decimal mark of displayed prices.
+    pub number_locale: NumberLocale,
+    /// How prices are rounded to the decimal places shown.
+    pub rounding_mode: RoundingMode,
+    /// Prefix the tray title with the exchange name, useful when the same
+    /// pair is monitored on more than one exchange.
+    pub show_exchange: bool,
+    /// How much of the pair and price the tray title shows.
+    pub title_style: TitleStyle,
+    /// Whether the tray title shows the price or its percentage change.
+    /// Toggled from the settings menu.
+    pub title_value: TitleValue,
+    /// Tray icon image, PNG or ICO. Relative paths are resolved against the
+    /// config file's directory, see [`Config::get_icon_path`]; the built-in
+    /// icon is used if it can't be loaded.
+    pub icon_path: String,
+    /// Icon shown instead while no pair is connected, from startup until the
+    /// first one connects and whenever the feed is down. Resolved like
+    /// `icon_path`; the main icon is used if unset or unreadable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_path_disconnected: Option<String>,
+    /// Icon shown for a while after a price alert fired, like
+    /// `icon_path_disconnected`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_path_alert: Option<String>,
+    /// Reload the config file when it changes on disk.
+    pub watch_config: bool,
+    /// Show "Disconnected" when no price arrived for this many seconds. Raised
+    /// to twice the longest update interval if that is longer, see
+    /// [`Config::stale_after`].
+    pub stale_after_secs: u64,
+    /// Appended to a pair's price once its newest update is older than
+    /// [`Config::stale_after`], e.g. `"⚠"`. Empty leaves stale prices unmarked.
+    pub stale_marker: String,
+    /// Show each pair in the tray title in turn, switching every this many
+    /// seconds. Unset shows the most recently updated pair.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_rotation_secs: Option<u64>,
+    /// A pair from `trading_pairs` the title always shows, whatever updated
+    /// last; the others are in the pairs menu. Turns off title rotation and
+    /// next-pair clicks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_pair: Option<String>,
+    /// Draw the price into the tray icon instead of showing it as the title.
+    pub render_price_in_icon: bool,
+    /// Colours of the price drawn into the icon, to suit a light or dark menu
+    /// bar. `auto` follows the system appearance as it changes.
+    pub icon_theme: IconTheme,
+    /// Give every pair its own tray icon, for up to eight pairs. The first
+    /// pair's icon has the menu and always shows that pair.
+    pub icon_per_pair: bool,
+    /// What clicking and double-clicking the tray icon do. A left click opens
+    /// the menu by default. Linux trays don't report clicks and always open
+    /// the menu.
+    pub left_click: ClickAction,
+    pub double_click: ClickAction,
+    /// Redraw the tray for new prices at most this often, showing the
+    /// latest. Timeouts and status changes still show right away.
+    pub min_redraw_ms: u64,
+    /// Show one volume-weighted price for a pair monitored on several
+    /// exchanges. The menu still lists each exchange.
+    pub aggregate_exchanges: bool,
+    /// Show prices quoted in USDT, USDC or USD in this currency instead, e.g.
+    /// `EUR`. Logged prices stay in the pair's own quote.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_currency: Option<String>,
+    /// Where the dollar rate of `display_currency` is fetched, hourly. The
+    /// answer must look like `{"rates": {"EUR": 0.92}}`; `{currency}` in the
+    /// URL is replaced with the currency code. Defaults to Frankfurter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fx_url: Option<String>,
+    /// Show the OKX account's total equity in dollars. Needs an API key, which
+    /// is only read from the environment or `secrets_file`, see
+    /// [`OkxCredentials`].
+    pub show_balance: bool,
+    /// A TOML file holding the OKX API key, kept apart from this config so
+    /// that the config can be shared. A relative path is resolved against
+    /// the config file's directory, or the working directory without one.
+    /// The key's environment variables take precedence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secrets_file: Option<String>,
+    /// Never read from or written to the config file.
+    #[serde(skip)]
+    pub okx_credentials: Option<OkxCredentials>,
+    /// The directory of the file this was loaded from, see `secrets_file`.
+    #[serde(skip)]
+    config_dir: Option<PathBuf>,
+    /// Number of recent prices kept per pair for the tooltip sparkline, and
+    /// of updates queued for each consumer such as the tray. A consumer that
+    /// falls behind skips updates. The queues are sized at startup.
+    pub max_buffer_size: usize,
+    /// Show the simple moving average of a pair's last this many prices in
+    /// the tooltip, once it has that many.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moving_average_window: Option<usize>,
+    /// Append every price update to this file as a JSON line. Read at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_log_path: Option<String>,
+    /// Save the last price of each pair to this file on quitting, and show
+    /// them, marked as old, at startup until new ones arrive. The last prices
+    /// are logged on quitting either way.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_prices_path: Option<String>,
+    /// Log how many updates each pair got, and how long they took from the
+    /// exchange, this often. Read at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics_interval_secs: Option<u64>,
+    /// Proxy for the exchanges' REST APIs, `http://` or `socks5://`. Falls
+    /// back to `HTTPS_PROXY` when unset. Read at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_url: Option<String>,
+    /// `User-Agent` header of the REST requests, [`DEFAULT_USER_AGENT`] if
+    /// unset. `exc` opens the websockets without one. Read at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// PEM file of extra root certificates to trust for the REST APIs, e.g.
+    /// a corporate proxy's CA. `exc` opens its websockets with its built-in
+    /// roots only, so the ticker streams don't use it. Read at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_cert_path: Option<String>,
+    /// Don't check the REST APIs' certificates at all. For development only;
+    /// a warning is logged whenever it is in effect. Read at startup.
+    pub danger_accept_invalid_certs: bool,
+    /// OKX websocket endpoint. Only OKX's own hosts are supported: the
+    /// default `wss://ws.okx.com:8443/ws/v5/public`, `wsaws.okx.com` (AWS) and
+    /// `wspap.okx.com` (demo trading). Read at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ws_url: Option<String>,
+    /// Stream OKX tickers from a local relay rebroadcasting OKX's `tickers`
+    /// channel instead: a `ws://` URL, or `unix:///path` for a Unix socket
+    /// with one message per line. Takes precedence over `ws_url`; Binance
+    /// pairs and the REST lookups still go to the exchanges. Read at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relay_url: Option<String>,
+    /// Seconds without a message from OKX after which its websocket is
+    /// pinged, and then again for the pong before it is reconnected. `exc`
+    /// has no separate ping interval; lower this to notice drops sooner.
+    /// Read at startup.
+    pub ws_ping_timeout_secs: u64,
+    /// Delay before reconnecting a pair, doubled with every consecutive
+    /// failure up to `max_backoff_secs`.
+    pub base_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    /// How the delays between reconnection attempts are drawn.
+    pub backoff_strategy: BackoffStrategy,
+    /// Consecutive failed attempts before a pair is reported as failed
+    /// instead of reconnecting. It keeps retrying either way.
+    pub max_consecutive_errors: u32,
+    /// Reconnects of a pair, over its whole run, after which it is given up
+    /// on and shown as stopped. Unset retries forever.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_total_retries: Option<u64>,
+    /// Ticker subscriptions sent to an exchange per second at most, so that
+    /// many pairs starting or reconnecting at once stay within its rate
+    /// limits. Read at startup.
+    pub subscribes_per_sec: u32,
+    /// Drop ticks whose price is the same as the pair's previous tick, as
+    /// exchanges sometimes resend unchanged tickers. They still count as a
+    /// sign of life. Turn off to forward every tick. Read at startup.
+    pub skip_repeated_ticks: bool,
+    /// Which of a ticker's prices is shown. Pairs for which it isn't
+    /// available show the last trade's price instead.
+    pub price_source: PriceSource,
+    /// Don't connect to the exchanges; show a reproducible random walk for
+    /// each pair instead, for trying the tray offline.
+    pub simulate: bool,
+    /// Desktop notifications when a pair crosses a price.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alerts: Vec<AlertRule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            trading_pairs: vec![PairConfig::new("BTC-USDT")],
+            update_interval_secs: 1,
+            decimals: None,
+            min_change_pct: None,
+            max_unchanged_secs: 60,
+            flash_threshold_pct: None,
+            change_baseline: ChangeBaseline::Day,
+            number_locale: NumberLocale::default(),
+            rounding_mode: RoundingMode::default(),
+            show_exchange: false,
+            title_style: TitleStyle::Full,
+            title_value: TitleValue::Price,
+            icon_path: "icons/icon.png".to_string(),
+            icon_path_disconnected: None,
+            icon_path_alert: None,
+            watch_config: false,
+            stale_after_secs: 30,
+            stale_marker: "(stale)".to_string(),
+            title_rotation_secs: None,
+            primary_pair: None,
+            render_price_in_icon: false,
+            icon_theme: IconTheme::Auto,
+            icon_per_pair: false,
+            left_click: ClickAction::None,
+            double_click: ClickAction::OpenExchange,
+            min_redraw_ms: 250,
+            aggregate_exchanges: false,
+            display_currency: None,
+            fx_url: None,
+            show_balance: false,
+            secrets_file: None,
+            okx_credentials: None,
+            config_dir: None,
+            max_buffer_size: 60,
+            moving_average_window: None,
+            price_log_path: None,
+            last_prices_path: None,
+            metrics_interval_secs: None,
+            proxy_url: None,
+            user_agent: None,
+            ca_cert_path: None,
+            danger_accept_invalid_certs: false,
+            ws_url: None,
+            relay_url: None,
+            ws_ping_timeout_secs: 5,
+            base_backoff_secs: 1,
+            max_backoff_secs: 60,
+            backoff_strategy: BackoffStrategy::Exponential,
+            max_consecutive_errors: 5,
+            max_total_retries: None,
+            subscribes_per_sec: 5,
+            skip_repeated_ticks: true,
+            price_source: PriceSource::Last,
+            simulate: false,
+            alerts: Vec::new(),
+        }
+    }
+}
+
+/// Config file formats, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// `.yaml`/`.yml` and `.json` files; anything else is read as TOML.
+    fn of(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            Some("yaml" | "yml") => Format::Yaml,
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+
+    fn parse(self, content: &str) -> std::result::Result<Config, String> {
+        match self {
+            Format::Toml => toml::from_str(content).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::from_str(content).map_err(|err| err.to_string()),
+            Format::Json => serde_json::from_str(content).map_err(|err| err.to_string()),
+        }
+    }
+
+    fn render(self, config: &Config) -> std::result::Result<String, String> {
+        match self {
+            Format::Toml => toml::to_string(config).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::to_string(config).map_err(|err| err.to_string()),
+            Format::Json => serde_json::to_string_pretty(config).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+impl Config {
+    /// Load and validate the configuration at `path`. The format follows the
+    /// extension: TOML, YAML (`.yaml`/`.yml`) or JSON (`.json`).
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            TickerError::ConfigError(format!("failed to read {}: {err}", path.display()))
+        })?;
+        let mut config: Config = Format::of(path).parse(&content).map_err(|err| {
+            TickerError::ConfigError(format!("failed to parse {}: {err}", path.display()))
+        })?;
+        config.config_dir = path.parent().map(Path::to_path_buf);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Build a config in code, starting from the defaults without any pair:
+    ///
+    /// ```ignore
+    /// let config = Config::builder()
+    ///     .add_pair("BTC-USDT")
+    ///     .add_pair("binance:ETH-USDT")
+    ///     .update_interval(Duration::from_secs(5))
+    ///     .title_style(TitleStyle::Compact)
+    ///     .build()?;
+    /// ```
+    #[allow(dead_code)]
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// The defaults overlaid with the `TICKER_*` environment variables, see
+    /// [`Config::merge_env`].
+    pub fn from_env() -> Result<Self> {
+        let mut config = Config::default();
+        config.merge_env()?;
+        Ok(config)
+    }
+
+    /// Override fields with the environment variables that are set, then
+    /// validate the result:
+    ///
+    /// | Variable                        | Field                            |
+    /// |---------------------------------|----------------------------------|
+    /// | `TICKER_TRADING_PAIRS`          | `trading_pairs`, comma-separated |
+    /// | `TICKER_UPDATE_INTERVAL_SECS`   | `update_interval_secs`           |
+    /// | `TICKER_DECIMALS`               | `decimals`                       |
+    /// | `TICKER_MIN_CHANGE_PCT`         | `min_change_pct`                 |
+    /// | `TICKER_MAX_UNCHANGED_SECS`     | `max_unchanged_secs`             |
+    /// | `TICKER_FLASH_THRESHOLD_PCT`    | `flash_threshold_pct`            |
+    /// | `TICKER_CHANGE_BASELINE`        | `change_baseline`                |
+    /// | `TICKER_NUMBER_LOCALE`          | `number_locale`                  |
+    /// | `TICKER_ROUNDING_MODE`          | `rounding_mode`                  |
+    /// | `TICKER_SHOW_EXCHANGE`          | `show_exchange`                  |
+    /// | `TICKER_TITLE_STYLE`            | `title_style`                    |
+    /// | `TICKER_TITLE_VALUE`            | `title_value`                    |
+    /// | `TICKER_ICON_PATH`              | `icon_path`                      |
+    /// | `TICKER_ICON_PATH_DISCONNECTED` | `icon_path_disconnected`         |
+    /// | `TICKER_ICON_PATH_ALERT`        | `icon_path_alert`                |
+    /// | `TICKER_WATCH_CONFIG`           | `watch_config`                   |
+    /// | `TICKER_STALE_AFTER_SECS`       | `stale_after_secs`               |
+    /// | `TICKER_STALE_MARKER`           | `stale_marker`                   |
+    /// | `TICKER_TITLE_ROTATION_SECS`    | `title_rotation_secs`            |
+    /// | `TICKER_PRIMARY_PAIR`           | `primary_pair`                   |
+    /// | `TICKER_RENDER_PRICE_IN_ICON`   | `render_price_in_icon`           |
+    /// | `TICKER_ICON_THEME`             | `icon_theme`                     |
+    /// | `TICKER_ICON_PER_PAIR`          | `icon_per_pair`                  |
+    /// | `TICKER_LEFT_CLICK`             | `left_click`                     |
+    /// | `TICKER_DOUBLE_CLICK`           | `double_click`                   |
+    /// | `TICKER_MIN_REDRAW_MS`          | `min_redraw_ms`                  |
+    /// | `TICKER_AGGREGATE_EXCHANGES`    | `aggregate_exchanges`            |
+    /// | `TICKER_DISPLAY_CURRENCY`       | `display_currency`               |
+    /// | `TICKER_FX_URL`                 | `fx_url`                         |
+    /// | `TICKER_SHOW_BALANCE`           | `show_balance`                   |
+    /// | `TICKER_SECRETS_FILE`           | `secrets_file`                   |
+    /// | `TICKER_MAX_BUFFER_SIZE`        | `max_buffer_size`                |
+    /// | `TICKER_MOVING_AVERAGE_WINDOW`  | `moving_average_window`          |
+    /// | `TICKER_PRICE_LOG_PATH`         | `price_log_path`                 |
+    /// | `TICKER_LAST_PRICES_PATH`       | `last_prices_path`               |
+    /// | `TICKER_METRICS_INTERVAL_SECS`  | `metrics_interval_secs`          |
+    /// | `TICKER_PROXY_URL`              | `proxy_url`                      |
+    /// | `TICKER_USER_AGENT`             | `user_agent`                     |
+    /// | `TICKER_CA_CERT_PATH`           | `ca_cert_path`                   |
+    /// | `TICKER_DANGER_ACCEPT_INVALID_CERTS` | `danger_accept_invalid_certs` |
+    /// | `TICKER_WS_URL`                 | `ws_url`                         |
+    /// | `TICKER_RELAY_URL`              | `relay_url`                      |
+    /// | `TICKER_WS_PING_TIMEOUT_SECS`   | `ws_ping_timeout_secs`           |
+    /// | `TICKER_BASE_BACKOFF_SECS`      | `base_backoff_secs`              |
+    /// | `TICKER_MAX_BACKOFF_SECS`       | `max_backoff_secs`               |
+    /// | `TICKER_BACKOFF_STRATEGY`       | `backoff_strategy`               |
+    /// | `TICKER_MAX_CONSECUTIVE_ERRORS` | `max_consecutive_errors`         |
+    /// | `TICKER_MAX_TOTAL_RETRIES`      | `max_total_retries`              |
+    /// | `TICKER_SUBSCRIBES_PER_SEC`     | `subscribes_per_sec`             |
+    /// | `TICKER_SKIP_REPEATED_TICKS`    | `skip_repeated_ticks`            |
+    /// | `TICKER_PRICE_SOURCE`           | `price_source`                   |
+    /// | `TICKER_SIMULATE`               | `simulate`                       |
+    ///
+    /// Flags accept `true`/`false`, `1`/`0`, `yes`/`no` and `on`/`off`.
+    pub fn merge_env(&mut self) -> Result<()> {
+        if let Some(pairs) = env_var::<String>("TICKER_TRADING_PAIRS")? {
+            self.trading_pairs = pairs
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(PairConfig::new)
+                .collect();
+        }
+        if let Some(secs) = env_var("TICKER_UPDATE_INTERVAL_SECS")? {
+            self.update_interval_secs = secs;
+        }
+        if let Some(decimals) = env_var("TICKER_DECIMALS")? {
+            self.decimals = Some(decimals);
+        }
+        if let Some(pct) = env_var("TICKER_MIN_CHANGE_PCT")? {
+            self.min_change_pct = Some(pct);
+        }
+        if let Some(secs) = env_var("TICKER_MAX_UNCHANGED_SECS")? {
+            self.max_unchanged_secs = secs;
+        }
+        if let Some(pct) = env_var("TICKER_FLASH_THRESHOLD_PCT")? {
+            self.flash_threshold_pct = Some(pct);
+        }
+        if let Some(baseline) = env_var("TICKER_CHANGE_BASELINE")? {
+            self.change_baseline = baseline;
+        }
+        if let Some(locale) = env_var("TICKER_NUMBER_LOCALE")? {
+            self.number_locale = locale;
+        }
+        if let Some(mode) = env_var("TICKER_ROUNDING_MODE")? {
+            self.rounding_mode = mode;
+        }
+        if let Some(show) = env_flag("TICKER_SHOW_EXCHANGE")? {
+            self.show_exchange = show;
+        }
+        if let Some(style) = env_var("TICKER_TITLE_STYLE")? {
+            self.title_style = style;
+        }
+        if let Some(value) = env_var("TICKER_TITLE_VALUE")? {
+            self.title_value = value;
+        }
+        if let Some(path) = env_var("TICKER_ICON_PATH")? {
+            self.icon_path = path;
+        }
+        if let Some(path) = env_var("TICKER_ICON_PATH_DISCONNECTED")? {
+            self.icon_path_disconnected = Some(path);
+        }
+        if let Some(path) = env_var("TICKER_ICON_PATH_ALERT")? {
+            self.icon_path_alert = Some(path);
+        }
+        if let Some(watch) = env_flag("TICKER_WATCH_CONFIG")? {
+            self.watch_config = watch;
+        }
+        if let Some(secs) = env_var("TICKER_STALE_AFTER_SECS")? {
+            self.stale_after_secs = secs;
+        }
+        if let Some(marker) = env_var("TICKER_STALE_MARKER")? {
+            self.stale_marker = marker;
+        }
+        if let Some(secs) = env_var("TICKER_TITLE_ROTATION_SECS")? {
+            self.title_rotation_secs = Some(secs);
+        }
+        if let Some(pair) = env_var("TICKER_PRIMARY_PAIR")? {
+            self.primary_pair = Some(pair);
+        }
+        if let Some(render) = env_flag("TICKER_RENDER_PRICE_IN_ICON")? {
+            self.render_price_in_icon = render;
+        }
+        if let Some(theme) = env_var("TICKER_ICON_THEME")? {
+            self.icon_theme = theme;
+        }
+        if let Some(per_pair) = env_flag("TICKER_ICON_PER_PAIR")? {
+            self.icon_per_pair = per_pair;
+        }
+        if let Some(action) = env_var("TICKER_LEFT_CLICK")? {
+            self.left_click = action;
+        }
+        if let Some(action) = env_var("TICKER_DOUBLE_CLICK")? {
+            self.double_click = action;
+        }
+        if let Some(ms) = env_var("TICKER_MIN_REDRAW_MS")? {
+            self.min_redraw_ms = ms;
+        }
+        if let Some(aggregate) = env_flag("TICKER_AGGREGATE_EXCHANGES")? {
+            self.aggregate_exchanges = aggregate;
+        }
+        if let Some(currency) = env_var("TICKER_DISPLAY_CURRENCY")? {
+            self.display_currency = Some(currency);
+        }
+        if let Some(url) = env_var("TICKER_FX_URL")? {
+            self.fx_url = Some(url);
+        }
+        if let Some(show) = env_flag("TICKER_SHOW_BALANCE")? {
+            self.show_balance = show;
+        }
+        if let Some(path) = env_var("TICKER_SECRETS_FILE")? {
+            self.secrets_file = Some(path);
+        }
+        self.okx_credentials = match (OkxCredentials::from_env()?, &self.secrets_file) {
+            (None, Some(path)) => OkxCredentials::from_secrets_file(&self.secrets_path(path))?,
+            (credentials, _) => credentials,
+        };
+        if let Some(size) = env_var("TICKER_MAX_BUFFER_SIZE")? {
+            self.max_buffer_size = size;
+        }
+        if let Some(window) = env_var("TICKER_MOVING_AVERAGE_WINDOW")? {
+            self.moving_average_window = Some(window);
+        }
+        if let Some(path) = env_var("TICKER_PRICE_LOG_PATH")? {
+            self.price_log_path = Some(path);
+        }
+        if let Some(path) = env_var("TICKER_LAST_PRICES_PATH")? {
+            self.last_prices_path = Some(path);
+        }
+        if let Some(secs) = env_var("TICKER_METRICS_INTERVAL_SECS")? {
+            self.metrics_interval_secs = Some(secs);
+        }
+        if let Some(url) = env_var("TICKER_PROXY_URL")? {
+            self.proxy_url = Some(url);
+        }
+        if let Some(agent) = env_var("TICKER_USER_AGENT")? {
+            self.user_agent = Some(agent);
+        }
+        if let Some(path) = env_var("TICKER_CA_CERT_PATH")? {
+            self.ca_cert_path = Some(path);
+        }
+        if let Some(accept) = env_flag("TICKER_DANGER_ACCEPT_INVALID_CERTS")? {
+            self.danger_accept_invalid_certs = accept;
+        }
+        if let Some(url) = env_var("TICKER_WS_URL")? {
+            self.ws_url = Some(url);
+        }
+        if let Some(url) = env_var("TICKER_RELAY_URL")? {
+            self.relay_url = Some(url);
+        }
+        if let Some(secs) = env_var("TICKER_WS_PING_TIMEOUT_SECS")? {
+            self.ws_ping_timeout_secs = secs;
+        }
+        if let Some(secs) = env_var("TICKER_BASE_BACKOFF_SECS")? {
+            self.base_backoff_secs = secs;
+        }
+        if let Some(secs) = env_var("TICKER_MAX_BACKOFF_SECS")? {
+            self.max_backoff_secs = secs;
+        }
+        if let Some(strategy) = env_var("TICKER_BACKOFF_STRATEGY")? {
+            self.backoff_strategy = strategy;
+        }
+        if let Some(count) = env_var("TICKER_MAX_CONSECUTIVE_ERRORS")? {
+            self.max_consecutive_errors = count;
+        }
+        if let Some(count) = env_var("TICKER_MAX_TOTAL_RETRIES")? {
+            self.max_total_retries = Some(count);
+        }
+        if let Some(rate) = env_var("TICKER_SUBSCRIBES_PER_SEC")? {
+            self.subscribes_per_sec = rate;
+        }
+        if let Some(skip) = env_flag("TICKER_SKIP_REPEATED_TICKS")? {
+            self.skip_repeated_ticks = skip;
+        }
+        if let Some(source) = env_var("TICKER_PRICE_SOURCE")? {
+            self.price_source = source;
+        }
+        if let Some(simulate) = env_flag("TICKER_SIMULATE")? {
+            self.simulate = simulate;
+        }
+        self.validate()
+    }
+
+    /// The configuration as TOML, as `config.toml` would hold it.
+    pub fn to_toml(&self) -> Result<String> {
+        Format::Toml.render(self).map_err(|err| {
+            TickerError::ConfigError(format!("failed to serialize the config: {err}"))
+        })
+    }
+
+    /// Write the configuration to `path`, in the format its extension selects.
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = Format::of(path).render(self).map_err(|err| {
+            TickerError::ConfigError(format!("failed to serialize {}: {err}", path.display()))
+        })?;
+        std::fs::write(path, content).map_err(|err| {
+            TickerError::ConfigError(format!("failed to write {}: {err}", path.display()))
+        })
+    }
+
+    /// Watch the file at `path` and send each new, valid version of it.
+    /// Edits that fail to parse or validate are logged and skipped. The
+    /// watcher thread exits once the receiver is dropped.
+    #[cfg(feature = "tray")]
+    pub fn watch(path: impl AsRef<Path>) -> Result<Receiver<Config>> {
+        let path = path.as_ref().to_path_buf();
+        let watch_error = |err: notify::Error| {
+            TickerError::ConfigError(format!("failed to watch {}: {err}", path.display()))
+        };
+
+        // Editors often replace the file instead of writing to it, which a watch
+        // on the file itself would miss, so watch its directory instead.
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = path.file_name().map(|name| name.to_os_string());
+        let (event_tx, event_rx) = channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                if !event.kind.is_access()
+                    && event
+                        .paths
+                        .iter()
+                        .any(|p| p.file_name() == file_name.as_deref())
+                {
+                    let _ = event_tx.send(());
+                }
+            }
+        })
+        .map_err(watch_error)?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+
+        let (config_tx, config_rx) = channel();
+        std::thread::spawn(move || {
+            let _watcher = watcher;
+            while event_rx.recv().is_ok() {
+                while event_rx.recv_timeout(RELOAD_DEBOUNCE).is_ok() {}
+                let reloaded = Config::from_file(&path).and_then(|mut config| {
+                    config.merge_env()?;
+                    Ok(config)
+                });
+                match reloaded {
+                    Ok(config) => {
+                        tracing::info!("reloaded {}", path.display());
+                        if config_tx.send(config).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => tracing::warn!("ignoring config change: {err}"),
+                }
+            }
+        });
+        Ok(config_rx)
+    }
+
+    /// Check the invariants serde can't express: at least one well-formed
+    /// pair and non-zero intervals. A missing icon file is not an error, the
+    /// built-in icon is shown instead.
+    pub fn validate(&self) -> Result<()> {
+        if self.trading_pairs.is_empty() {
+            return Err(TickerError::ConfigError(
+                "trading_pairs must contain at least one pair".to_string(),
+            ));
+        }
+        if self.update_interval_secs == 0 {
+            return Err(TickerError::ConfigError(
+                "update_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.stale_after_secs == 0 {
+            return Err(TickerError::ConfigError(
+                "stale_after_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.ws_ping_timeout_secs == 0 {
+            return Err(TickerError::ConfigError(
+                "ws_ping_timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.base_backoff_secs == 0 {
+            return Err(TickerError::ConfigError(
+                "base_backoff_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.max_backoff_secs < self.base_backoff_secs {
+            return Err(TickerError::ConfigError(
+                "max_backoff_secs must be at least base_backoff_secs".to_string(),
+            ));
+        }
+        if self.max_consecutive_errors == 0 {
+            return Err(TickerError::ConfigError(
+                "max_consecutive_errors must be greater than 0".to_string(),
+            ));
+        }
+        if self.subscribes_per_sec == 0 {
+            return Err(TickerError::ConfigError(
+                "subscribes_per_sec must be greater than 0".to_string(),
+            ));
+        }
+        if self.max_buffer_size == 0 {
+            return Err(TickerError::ConfigError(
+                "max_buffer_size must be greater than 0".to_string(),
+            ));
+        }
+        if self.moving_average_window == Some(0) {
+            return Err(TickerError::ConfigError(
+                "moving_average_window must be greater than 0".to_string(),
+            ));
+        }
+        if self
+            .min_change_pct
+            .is_some_and(|pct| pct.is_sign_negative())
+        {
+            return Err(TickerError::ConfigError(
+                "min_change_pct must not be negative".to_string(),
+            ));
+        }
+        if self
+            .flash_threshold_pct
+            .is_some_and(|pct| pct.is_sign_negative() || pct.is_zero())
+        {
+            return Err(TickerError::ConfigError(
+                "flash_threshold_pct must be greater than 0".to_string(),
+            ));
+        }
+        if self.title_rotation_secs == Some(0) {
+            return Err(TickerError::ConfigError(
+                "title_rotation_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.metrics_interval_secs == Some(0) {
+            return Err(TickerError::ConfigError(
+                "metrics_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+        for pair_config in &self.trading_pairs {
+            validate_pair(&pair_config.pair, pair_config.instrument_type)?;
+            if pair_config.interval_secs == Some(0) {
+                return Err(TickerError::ConfigError(format!(
+                    "trading_pairs: interval_secs for {} must be greater than 0",
+                    pair_config.pair
+                )));
+            }
+            if pair_config
+                .min_change_pct
+                .is_some_and(|pct| pct.is_sign_negative())
+            {
+                return Err(TickerError::ConfigError(format!(
+                    "trading_pairs: min_change_pct for {} must not be negative",
+                    pair_config.pair
+                )));
+            }
+            if pair_config
+                .reference_price
+                .is_some_and(|price| price <= Decimal::ZERO)
+            {
+                return Err(TickerError::ConfigError(format!(
+                    "trading_pairs: reference_price for {} must be greater than 0",
+                    pair_config.pair
+                )));
+            }
+        }
+        if let Some(primary) = &self.primary_pair {
+            let key = exchange::parse_pair(primary).map_err(|err| {
+                TickerError::ConfigError(format!("primary_pair: {primary}: {err}"))
+            })?;
+            let listed = self.trading_pairs.iter().any(|pair_config| {
+                exchange::parse_pair(&pair_config.pair).ok() == Some(key.clone())
+            });
+            if !listed {
+                return Err(TickerError::ConfigError(format!(
+                    "primary_pair: `{primary}` is not in trading_pairs"
+                )));
+            }
+        }
+        for alert in &self.alerts {
+            validate_pair(&alert.pair, InstrumentType::of(&alert.pair))?;
+            if alert.above.is_none() && alert.below.is_none() {
+                return Err(TickerError::ConfigError(format!(
+                    "alerts: the alert for {} needs `above` or `below`",
+                    alert.pair
+                )));
+            }
+        }
+        if let Some(agent) = &self.user_agent {
+            if agent.trim().is_empty() || reqwest::header::HeaderValue::from_str(agent).is_err() {
+                return Err(TickerError::ConfigError(format!(
+                    "user_agent: `{agent}` is not a valid header value"
+                )));
+            }
+        }
+        if let Some(url) = &self.proxy_url {
+            let supported = ["http://", "https://", "socks5://", "socks5h://"]
+                .iter()
+                .any(|scheme| url.starts_with(scheme));
+            if !supported || reqwest::Proxy::all(url).is_err() {
+                return Err(TickerError::ConfigError(format!(
+                    "proxy_url: `{url}` is not an http:// or socks5:// URL"
+                )));
+            }
+        }
+        if let Some(url) = &self.ws_url {
+            exchange::OkxHost::from_url(url)
+                .map_err(|err| TickerError::ConfigError(format!("ws_url: `{url}`: {err}")))?;
+        }
+        if let Some(url) = &self.relay_url {
+            relay::RelayUrl::parse(url)
+                .map_err(|err| TickerError::ConfigError(format!("relay_url: `{url}`: {err}")))?;
+        }
+        if let Some(currency) = &self.display_currency {
+            if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(TickerError::ConfigError(format!(
+                    "display_currency: `{currency}` is not a currency code such as EUR"
+                )));
+            }
+        }
+        if let Some(url) = &self.fx_url {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(TickerError::ConfigError(format!(
+                    "fx_url: `{url}` is not an http:// URL"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// `icon_path`, a relative one resolved against the directory of
+    /// `config_path`, then of the executable, then `../share/okk` from there
+    /// as installed by packages, and in debug builds against the source
+    /// checkout, for `cargo run`. Falls back to the config directory if
+    /// nothing matches. Windows paths such as `C:\icons\icon.png` are
+    /// absolute on every platform.
+    #[cfg(feature = "tray")]
+    pub fn get_icon_path(&self, config_path: &Path) -> PathBuf {
+        resolve_icon_path(&self.icon_path, config_path)
+    }
+
+    /// `secrets_file` resolved against the config file's directory.
+    fn secrets_path(&self, path: &str) -> PathBuf {
+        match &self.config_dir {
+            Some(dir) if !is_absolute_path(path) => dir.join(path),
+            _ => PathBuf::from(path),
+        }
+    }
+
+    /// `icon_path_disconnected`, resolved like [`Config::get_icon_path`].
+    #[cfg(feature = "tray")]
+    pub fn get_icon_path_disconnected(&self, config_path: &Path) -> Option<PathBuf> {
+        let path = self.icon_path_disconnected.as_ref()?;
+        Some(resolve_icon_path(path, config_path))
+    }
+
+    /// `icon_path_alert`, resolved like [`Config::get_icon_path`].
+    #[cfg(feature = "tray")]
+    pub fn get_icon_path_alert(&self, config_path: &Path) -> Option<PathBuf> {
+        let path = self.icon_path_alert.as_ref()?;
+        Some(resolve_icon_path(path, config_path))
+    }
+
+    pub fn interval_for(&self, pair: &PairConfig) -> Duration {
+        Duration::from_secs(pair.interval_secs.unwrap_or(self.update_interval_secs))
+    }
+
+    /// How long prices may be missing before the feed counts as stalled. A pair
+    /// updating every minute must not look disconnected between updates, so
+    /// this is at least twice the longest update interval.
+    #[cfg(feature = "tray")]
+    pub fn stale_after(&self) -> Duration {
+        let longest_interval = self
+            .trading_pairs
+            .iter()
+            .map(|pair| self.interval_for(pair))
+            .max()
+            .unwrap_or_default();
+        Duration::from_secs(self.stale_after_secs).max(longest_interval * 2)
+    }
+
+    /// The configured proxy, or else the one in `HTTPS_PROXY`.
+    pub fn proxy_url(&self) -> Option<String> {
+        self.proxy_url.clone().or_else(|| {
+            ["HTTPS_PROXY", "https_proxy"]
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+                .filter(|url| !url.is_empty())
+        })
+    }
+
+    pub fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+
+    #[cfg(feature = "tray")]
+    pub fn min_change_for(&self, pair: &PairConfig) -> Option<Decimal> {
+        pair.min_change_pct.or(self.min_change_pct)
+    }
+}
+
+/// An OKX API key, for reading the account balance. It only comes from
+/// `TICKER_OKX_API_KEY`, `TICKER_OKX_API_SECRET` and
+/// `TICKER_OKX_API_PASSPHRASE`, or the `secrets_file`, so that it doesn't end
+/// up in a config file, and `Debug` leaves it out so that it doesn't end up
+/// in a log. Only the tray's balance uses it.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "tray"), allow(dead_code))]
+pub struct OkxCredentials {
+    pub key: String,
+    pub secret: String,
+    pub passphrase: String,
+}
+
+impl OkxCredentials {
+    const VARS: [&'static str; 3] = [
+        "TICKER_OKX_API_KEY",
+        "TICKER_OKX_API_SECRET",
+        "TICKER_OKX_API_PASSPHRASE",
+    ];
+
+    /// The fields of the secrets file, the variables' names in lower case
+    /// without the prefix.
+    const SECRETS_FILE_KEYS: [&'static str; 3] =
+        ["okx_api_key", "okx_api_secret", "okx_api_passphrase"];
+
+    /// The key if all three variables are set, `None` if none is.
+    fn from_env() -> Result<Option<Self>> {
+        Self::from_parts(Self::VARS.map(|name| std::env::var(name).ok()), Self::VARS)
+    }
+
+    /// The key in the TOML file at `path`, like [`OkxCredentials::from_env`]:
+    ///
+    /// ```toml
+    /// okx_api_key = "..."
+    /// okx_api_secret = "..."
+    /// okx_api_passphrase = "..."
+    /// ```
+    ///
+    /// Warns if anyone on the system may read the file.
+    fn from_secrets_file(path: &Path) -> Result<Option<Self>> {
+        let read_error = |err: &dyn Display| {
+            TickerError::ConfigError(format!("secrets_file: {}: {err}", path.display()))
+        };
+        let content = std::fs::read_to_string(path).map_err(|err| read_error(&err))?;
+        warn_if_world_readable(path);
+        let table: toml::Table = toml::from_str(&content).map_err(|err| read_error(&err))?;
+        let values = Self::SECRETS_FILE_KEYS.map(|key| {
+            table
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        });
+        Self::from_parts(values, Self::SECRETS_FILE_KEYS)
+    }
+
+    /// The key if all three of `values` are set, `None` if none is. `names`
+    /// are where they come from, for the error.
+    fn from_parts(values: [Option<String>; 3], names: [&str; 3]) -> Result<Option<Self>> {
+        let [key, secret, passphrase] = values.map(|value| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        });
+        match (key, secret, passphrase) {
+            (Some(key), Some(secret), Some(passphrase)) => Ok(Some(Self {
+                key,
+                secret,
+                passphrase,
+            })),
+            (None, None, None) => Ok(None),
+            _ => Err(TickerError::ConfigError(format!(
+                "{} must be set together",
+                names.join(", ")
+            ))),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn warn_if_world_readable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = std::fs::metadata(path) {
+        if metadata.permissions().mode() & 0o004 != 0 {
+            warn_always(&format!(
+                "{} is readable by every user; restrict it with `chmod 600`",
+                path.display()
+            ));
+        }
+    }
+}
+
+/// Other systems' access rules aren't checked.
+#[cfg(not(unix))]
+fn warn_if_world_readable(_path: &Path) {}
+
+/// Log a warning about an insecure setting, straight to stderr if `RUST_LOG`
+/// filters out warnings, so it can't go unseen.
+pub fn warn_always(message: &str) {
+    if tracing::enabled!(tracing::Level::WARN) {
+        tracing::warn!("{message}");
+    } else {
+        eprintln!("warning: {message}");
+    }
+}
+
+impl std::fmt::Debug for OkxCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OkxCredentials(..)")
+    }
+}
+
+/// See [`Config::get_icon_path`].
+#[cfg(feature = "tray")]
+fn resolve_icon_path(path: &str, config_path: &Path) -> PathBuf {
+    find_icon(path, config_path, std::env::current_exe().ok().as_deref())
+}
+
+/// [`resolve_icon_path`] for the executable at `exe`.
+#[cfg(feature = "tray")]
+fn find_icon(path: &str, config_path: &Path, exe: Option<&Path>) -> PathBuf {
+    if is_absolute_path(path) {
+        return PathBuf::from(path);
+    }
+    let path = Path::new(path);
+    let config_dir = config_path.parent().unwrap_or(Path::new("")).join(path);
+    let exe_dirs = exe.and_then(Path::parent).map(|dir| {
+        [
+            dir.join(path),
+            dir.join("../share").join(env!("CARGO_PKG_NAME")).join(path),
+        ]
+    });
+    // The checkout only exists where the binary was built; an installed
+    // release build has no business looking there.
+    let manifest_dir =
+        cfg!(debug_assertions).then(|| Path::new(env!("CARGO_MANIFEST_DIR")).join(path));
+    std::iter::once(config_dir.clone())
+        .chain(exe_dirs.into_iter().flatten())
+        .chain(manifest_dir)
+        .find(|candidate| candidate.is_file())
+        .unwrap_or(config_dir)
+}
+
+/// Whether `path` is absolute on any platform, so that a config written on
+/// Windows is read the same elsewhere: `/...`, `C:\...`, `C:/...`, and
+/// `\...` including `\\server\share`. `C:icon.png` is relative to the
+/// drive's current directory, and treated as relative.
+fn is_absolute_path(path: &str) -> bool {
+    let drive = matches!(
+        path.as_bytes(),
+        [letter, b':', b'/' | b'\\', ..] if letter.is_ascii_alphabetic()
+    );
+    drive || path.starts_with('/') || path.starts_with('\\') || Path::new(path).is_absolute()
+}
+
+/// Read and parse the environment variable `name`, `None` if it is unset.
+fn env_var<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = match std::env::var(name) {
+        Ok(value) => value,
+        Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(err) => return Err(TickerError::ConfigError(format!("{name}: {err}"))),
+    };
+    value
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|err| TickerError::ConfigError(format!("{name}: invalid value `{value}`: {err}")))
+}
+
+fn env_flag(name: &str) -> Result<Option<bool>> {
+    let Some(value) = env_var::<String>(name)? else {
+        return Ok(None);
+    };
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(Some(true)),
+        "0" | "false" | "no" | "off" => Ok(Some(false)),
+        _ => Err(TickerError::ConfigError(format!(
+            "{name}: invalid value `{value}`: expected true or false"
+        ))),
+    }
+}
+
+/// Sets up a [`Config`] field by field, see [`Config::builder`]. The binary
+/// reads its config from files; this is for code embedding the ticker.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self {
+            config: Config {
+                trading_pairs: Vec::new(),
+                ..Config::default()
+            },
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl ConfigBuilder {
+    /// Monitor `pair`, written as in `trading_pairs`.
+    pub fn add_pair(self, pair: impl Into<String>) -> Self {
+        self.add_pair_config(PairConfig::new(pair))
+    }
+
+    /// Monitor a pair with its own overrides.
+    pub fn add_pair_config(mut self, pair: PairConfig) -> Self {
+        self.config.trading_pairs.push(pair);
+        self
+    }
+
+    /// Whole seconds; anything shorter is rejected by [`ConfigBuilder::build`].
+    pub fn update_interval(mut self, interval: Duration) -> Self {
+        self.config.update_interval_secs = interval.as_secs();
+        self
+    }
+
+    pub fn decimals(mut self, decimals: u32) -> Self {
+        self.config.decimals = Some(decimals);
+        self
+    }
+
+    pub fn show_exchange(mut self, show: bool) -> Self {
+        self.config.show_exchange = show;
+        self
+    }
+
+    pub fn title_style(mut self, style: TitleStyle) -> Self {
+        self.config.title_style = style;
+        self
+    }
+
+    pub fn title_value(mut self, value: TitleValue) -> Self {
+        self.config.title_value = value;
+        self
+    }
+
+    /// What the tooltip's percentage change is measured against.
+    pub fn change_baseline(mut self, baseline: ChangeBaseline) -> Self {
+        self.config.change_baseline = baseline;
+        self
+    }
+
+    pub fn number_locale(mut self, locale: NumberLocale) -> Self {
+        self.config.number_locale = locale;
+        self
+    }
+
+    pub fn rounding_mode(mut self, mode: RoundingMode) -> Self {
+        self.config.rounding_mode = mode;
+        self
+    }
+
+    /// Whole seconds, like [`ConfigBuilder::update_interval`].
+    pub fn stale_after(mut self, after: Duration) -> Self {
+        self.config.stale_after_secs = after.as_secs();
+        self
+    }
+
+    pub fn alert(mut self, rule: AlertRule) -> Self {
+        self.config.alerts.push(rule);
+        self
+    }
+
+    pub fn proxy_url(mut self, url: impl Into<String>) -> Self {
+        self.config.proxy_url = Some(url.into());
+        self
+    }
+
+    /// Made-up prices instead of the exchanges', see `simulate`.
+    pub fn simulate(mut self, simulate: bool) -> Self {
+        self.config.simulate = simulate;
+        self
+    }
+
+    /// The config, once it passes [`Config::validate`].
+    pub fn build(self) -> Result<Config> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// A pair must be `BASE-QUOTE` with alphanumeric parts, optionally prefixed
+/// with a supported exchange. Swaps and futures, only listed on OKX, add a
+/// `-SWAP` or `-YYMMDD` suffix.
+fn validate_pair(spec: &str, instrument_type: InstrumentType) -> Result<()> {
+    let (exchange, pair) = exchange::parse_pair(spec)
+        .map_err(|err| TickerError::ConfigError(format!("trading_pairs: {spec}: {err}")))?;
+    if instrument_type != InstrumentType::Spot && exchange != Exchange::Okx {
+        return Err(TickerError::ConfigError(format!(
+            "trading_pairs: `{spec}`: {instrument_type} instruments are only supported on okx"
+        )));
+    }
+    let kind = InstrumentType::of(&pair);
+    if instrument_type == InstrumentType::Spot && kind != InstrumentType::Spot {
+        return Err(TickerError::ConfigError(format!(
+            "trading_pairs: `{spec}` is a {kind} instrument; set instrument_type = \"{kind}\""
+        )));
+    }
+    let parts: Vec<&str> = pair.split('-').collect();
+    let (base_quote, kind) = match instrument_type {
+        InstrumentType::Spot => (&parts[..], InstrumentType::Spot),
+        _ => (&parts[..parts.len().min(2)], kind),
+    };
+    let well_formed = kind == instrument_type
+        && base_quote.len() == 2
+        && base_quote
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !well_formed {
+        return Err(TickerError::ConfigError(format!(
+            "trading_pairs: `{spec}` is not of the form {}",
+            instrument_type.shape()
+        )));
+    }
+    Ok(())
+}
+
+/// Notify once each time `pair` rises to `above` or falls to `below`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub pair: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub above: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub below: Option<Decimal>,
+}
+
+/// What a click on the tray icon does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClickAction {
+    /// Nothing; on macOS a left click then opens the menu.
+    None,
+    /// Show the next pair in the title. Without `title_rotation_secs` it
+    /// stays there instead of following the latest update.
+    NextPair,
+    /// Open the trading page of the pair in the title.
+    OpenExchange,
+    /// Open the config file in the default editor.
+    EditConfig,
+}
+
+impl ClickAction {
+    const ALL: [ClickAction; 4] = [
+        Self::None,
+        Self::NextPair,
+        Self::OpenExchange,
+        Self::EditConfig,
+    ];
+}
+
+impl Display for ClickAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::NextPair => write!(f, "next-pair"),
+            Self::OpenExchange => write!(f, "open-exchange"),
+            Self::EditConfig => write!(f, "edit-config"),
+        }
+    }
+}
+
+impl FromStr for ClickAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unsupported click action {s}, expected one of none, next-pair, \
+                     open-exchange, edit-config"
+                )
+            })
+    }
+}
+
+/// How much the tray title shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleStyle {
+    /// `BTC-USDT: 65000.10`, with the exchange if `show_exchange` is set.
+    #[default]
+    Full,
+    /// `BTC 65k`: the base currency and the price shortened with k, M or B.
+    Compact,
+    /// `BTC`, leaving the direction arrow as the only sign of the price.
+    SymbolOnly,
+}
+
+impl TitleStyle {
+    const ALL: [TitleStyle; 3] = [Self::Full, Self::Compact, Self::SymbolOnly];
+}
+
+impl Display for TitleStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Full => write!(f, "full"),
+            Self::Compact => write!(f, "compact"),
+            Self::SymbolOnly => write!(f, "symbol_only"),
+        }
+    }
+}
+
+impl FromStr for TitleStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|style| style.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!("unsupported title style {s}, expected one of full, compact, symbol_only")
+            })
+    }
+}
+
+/// What the tray title shows of the pair.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleValue {
+    /// `BTC-USDT: 65000.10`
+    #[default]
+    Price,
+    /// `BTC-USDT: +2.3%`, against the `change_baseline`. The price is shown
+    /// until the change is known.
+    Change,
+}
+
+impl TitleValue {
+    const ALL: [TitleValue; 2] = [Self::Price, Self::Change];
+}
+
+impl Display for TitleValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Price => write!(f, "price"),
+            Self::Change => write!(f, "change"),
+        }
+    }
+}
+
+impl FromStr for TitleValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|value| value.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unsupported title value {s}, expected one of price, change"))
+    }
+}
+
+/// The menu bar the icon colours are picked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IconTheme {
+    /// Dark text for a light menu bar.
+    Light,
+    /// Light text for a dark menu bar.
+    Dark,
+    /// Whichever the system appearance is, checked again every few seconds.
+    /// Falls back to `dark` where it can't be told.
+    #[default]
+    Auto,
+}
+
+impl IconTheme {
+    const ALL: [IconTheme; 3] = [Self::Light, Self::Dark, Self::Auto];
+}
+
+impl Display for IconTheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Light => write!(f, "light"),
+            Self::Dark => write!(f, "dark"),
+            Self::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl FromStr for IconTheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|theme| theme.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unsupported icon theme {s}, expected one of light, dark, auto"))
+    }
+}
+
+/// What the percentage change of a price is measured against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeBaseline {
+    /// The price 24 hours ago, as the exchange reports it.
+    #[default]
+    Day,
+    /// The first price seen since the app started.
+    Session,
+    /// The pair's `reference_price`. Pairs without one use `Day`.
+    CustomPrice,
+}
+
+impl ChangeBaseline {
+    const ALL: [ChangeBaseline; 3] = [Self::Day, Self::Session, Self::CustomPrice];
+}
+
+impl Display for ChangeBaseline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Day => write!(f, "day"),
+            Self::Session => write!(f, "session"),
+            Self::CustomPrice => write!(f, "custom_price"),
+        }
+    }
+}
+
+impl FromStr for ChangeBaseline {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|baseline| baseline.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unsupported change baseline {s}, expected one of day, session, custom_price"
+                )
+            })
+    }
+}
+
+/// How the delay before a reconnection attempt is drawn, from the backoff's
+/// base and max and the number of failures in a row. See
+/// <https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/>.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackoffStrategy {
+    /// `base * 2^failures` up to `max`, plus up to half of that again.
+    #[default]
+    Exponential,
+    /// Anywhere between zero and the exponential delay.
+    FullJitter,
+    /// Anywhere between `base` and three times the previous delay, up to
+    /// `max`, so the delays of different pairs drift apart.
+    Decorrelated,
+}
+
+impl BackoffStrategy {
+    const ALL: [BackoffStrategy; 3] = [Self::Exponential, Self::FullJitter, Self::Decorrelated];
+}
+
+impl Display for BackoffStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exponential => write!(f, "exponential"),
+            Self::FullJitter => write!(f, "full_jitter"),
+            Self::Decorrelated => write!(f, "decorrelated"),
+        }
+    }
+}
+
+impl FromStr for BackoffStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|strategy| strategy.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!(
+                    "unsupported backoff strategy {s}, expected one of exponential, \
+                     full_jitter, decorrelated"
+                )
+            })
+    }
+}
+
+/// Which price of a pair's ticker is shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    /// The last trade's price.
+    #[default]
+    Last,
+    /// OKX's mark price, which swaps and futures are liquidated at, looked
+    /// up every few seconds.
+    Mark,
+    /// OKX's index price of the underlying, looked up every few seconds.
+    Index,
+    /// Halfway between the best bid and ask.
+    Mid,
+}
+
+impl PriceSource {
+    const ALL: [PriceSource; 4] = [Self::Last, Self::Mark, Self::Index, Self::Mid];
+}
+
+impl Display for PriceSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Last => write!(f, "last"),
+            Self::Mark => write!(f, "mark"),
+            Self::Index => write!(f, "index"),
+            Self::Mid => write!(f, "mid"),
+        }
+    }
+}
+
+impl FromStr for PriceSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|source| source.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!("unsupported price source {s}, expected one of last, mark, index, mid")
+            })
+    }
+}
+
+/// How a pair's prices are received.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamMode {
+    /// Every trade from the websocket ticker.
+    #[default]
+    Ticker,
+    /// The last 1 minute candle, polled from the REST API.
+    Candles,
+}
+
+/// What kind of instrument a pair is. The kind is part of an OKX instrument
+/// name, which [`InstrumentType::of`] reads; declaring it on a pair makes
+/// sure the name is what was meant.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstrumentType {
+    /// `BTC-USDT`
+    #[default]
+    Spot,
+    /// A perpetual swap, `BTC-USDT-SWAP`. OKX only.
+    Swap,
+    /// A futures contract expiring on a date, `BTC-USD-240628`. OKX only.
+    Futures,
+}
+
+impl InstrumentType {
+    const ALL: [InstrumentType; 3] = [Self::Spot, Self::Swap, Self::Futures];
+
+    /// The kind of instrument `pair` names: a `-SWAP` suffix is a swap, a
+    /// `-YYMMDD` one a futures contract, and anything else spot.
+    pub fn of(pair: &str) -> Self {
+        let parts: Vec<&str> = pair.split('-').collect();
+        match parts[..] {
+            [_, _, suffix] if suffix.eq_ignore_ascii_case("SWAP") => Self::Swap,
+            [_, _, suffix] if suffix.len() == 6 && suffix.chars().all(|c| c.is_ascii_digit()) => {
+                Self::Futures
+            }
+            _ => Self::Spot,
+        }
+    }
+
+    /// The shape of the pair's name, for error messages.
+    fn shape(self) -> &'static str {
+        match self {
+            Self::Spot => "BASE-QUOTE",
+            Self::Swap => "BASE-QUOTE-SWAP",
+            Self::Futures => "BASE-QUOTE-YYMMDD",
+        }
+    }
+}
+
+impl Display for InstrumentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spot => write!(f, "spot"),
+            Self::Swap => write!(f, "swap"),
+            Self::Futures => write!(f, "futures"),
+        }
+    }
+}
+
+impl FromStr for InstrumentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!("unsupported instrument type {s}, expected one of spot, swap, futures")
+            })
+    }
+}
+
+/// A monitored pair with optional overrides of the global settings. In TOML
+/// either a plain string (`"BTC-USDT"`) or a table
+/// (`{ pair = "SHIB-USDT", interval_secs = 5, decimals = 8, stream = "candles" }`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "PairEntry", into = "PairEntry")]
+pub struct PairConfig {
+    pub pair: String,
+    pub interval_secs: Option<u64>,
+    pub decimals: Option<u32>,
+    pub min_change_pct: Option<Decimal>,
+    pub stream: StreamMode,
+    /// The price the change is measured against with
+    /// `change_baseline = "custom_price"`, e.g. what it was bought at.
+    pub reference_price: Option<Decimal>,
+    /// Checked against the pair's name, see [`validate_pair`].
+    pub instrument_type: InstrumentType,
+}
+
+impl PairConfig {
+    pub fn new(pair: impl Into<String>) -> Self {
+        Self {
+            pair: pair.into(),
+            interval_secs: None,
+            decimals: None,
+            min_change_pct: None,
+            stream: StreamMode::Ticker,
+            reference_price: None,
+            instrument_type: InstrumentType::Spot,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum PairEntry {
+    Plain(String),
+    Table {
+        pair: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval_secs: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        decimals: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_change_pct: Option<Decimal>,
+        #[serde(default, skip_serializing_if = "is_ticker")]
+        stream: StreamMode,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reference_price: Option<Decimal>,
+        #[serde(default, skip_serializing_if = "is_spot")]
+        instrument_type: InstrumentType,
+    },
+}
+
+impl From<PairEntry> for PairConfig {
+    fn from(entry: PairEntry) -> Self {
+        match entry {
+            PairEntry::Plain(pair) => PairConfig::new(pair),
+            PairEntry::Table {
+                pair,
+                interval_secs,
+                decimals,
+                min_change_pct,
+                stream,
+                reference_price,
+                instrument_type,
+            } => PairConfig {
+                pair,
+                interval_secs,
+                decimals,
+                min_change_pct,
+                stream,
+                reference_price,
+                instrument_type,
+            },
+        }
+    }
+}
+
+impl From<PairConfig> for PairEntry {
+    fn from(config: PairConfig) -> Self {
+        match config {
+            PairConfig {
+                pair,
+                interval_secs: None,
+                decimals: None,
+                min_change_pct: None,
+                stream: StreamMode::Ticker,
+                reference_price: None,
+                instrument_type: InstrumentType::Spot,
+            } => PairEntry::Plain(pair),
+            PairConfig {
+                pair,
+                interval_secs,
+                decimals,
+                min_change_pct,
+                stream,
+                reference_price,
+                instrument_type,
+            } => PairEntry::Table {
+                pair,
+                interval_secs,
+                decimals,
+                min_change_pct,
+                stream,
+                reference_price,
+                instrument_type,
+            },
+        }
+    }
+}
+
+fn is_ticker(stream: &StreamMode) -> bool {
+    *stream == StreamMode::Ticker
+}
+
+fn is_spot(instrument_type: &InstrumentType) -> bool {
+    *instrument_type == InstrumentType::Spot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("okk-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        /// Create an empty file at `path` under the directory.
+        fn touch(&self, path: &str) -> PathBuf {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, b"").unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn absolute_icon_paths_are_kept() {
+        for path in [
+            "/usr/share/okk/icon.png",
+            r"C:\icons\icon.png",
+            "c:/icons/icon.png",
+            r"\\server\share\icon.png",
+        ] {
+            assert!(is_absolute_path(path), "{path}");
+            let config = Path::new("/etc/okk/config.toml");
+            assert_eq!(find_icon(path, config, None), PathBuf::from(path));
+        }
+        for path in [
+            "icons/icon.png",
+            r"icons\icon.png",
+            "C:icon.png",
+            "./icon.png",
+        ] {
+            assert!(!is_absolute_path(path), "{path}");
+        }
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn relative_icon_paths_are_found_next_to_the_config_then_the_binary() {
+        let dir = TempDir::new("icon-lookup");
+        let config = dir.0.join("config/config.toml");
+        let exe = dir.touch("bin/okk");
+
+        let installed = dir.touch("share/okk/icons/test-icon.png");
+        let found = find_icon("icons/test-icon.png", &config, Some(&exe));
+        assert_eq!(
+            found.canonicalize().unwrap(),
+            installed.canonicalize().unwrap()
+        );
+
+        let beside_exe = dir.touch("bin/icons/test-icon.png");
+        assert_eq!(
+            find_icon("icons/test-icon.png", &config, Some(&exe)),
+            beside_exe
+        );
+
+        let beside_config = dir.touch("config/icons/test-icon.png");
+        assert_eq!(
+            find_icon("icons/test-icon.png", &config, Some(&exe)),
+            beside_config
+        );
+    }
+
+    #[test]
+    fn secrets_file_is_relative_to_the_config() {
+        let dir = TempDir::new("secrets-file");
+        let secrets = dir.touch("secrets.toml");
+        std::fs::write(
+            &secrets,
+            "okx_api_key = \"k\"\nokx_api_secret = \"s\"\nokx_api_passphrase = \"p\"\n",
+        )
+        .unwrap();
+        let config_path = dir.touch("config.toml");
+        std::fs::write(&config_path, "secrets_file = \"secrets.toml\"\n").unwrap();
+
+        let config = Config::from_file(&config_path).unwrap();
+        assert_eq!(config.secrets_path("secrets.toml"), secrets);
+        assert_eq!(
+            config.secrets_path("/etc/okk/secrets.toml"),
+            PathBuf::from("/etc/okk/secrets.toml")
+        );
+        let credentials = OkxCredentials::from_secrets_file(&config.secrets_path("secrets.toml"));
+        assert!(credentials.unwrap().is_some());
+        assert_eq!(
+            Config::default().secrets_path("secrets.toml"),
+            PathBuf::from("secrets.toml")
+        );
+    }
+
+    /// The error `validate` reports after `change` is applied to the defaults.
+    fn invalid(change: impl FnOnce(&mut Config)) -> String {
+        let mut config = Config::default();
+        change(&mut config);
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn validate_accepts_the_defaults() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn validate_rejects_broken_invariants() {
+        let err = invalid(|config| config.trading_pairs.clear());
+        assert!(err.contains("at least one pair"), "{err}");
+        let err = invalid(|config| config.update_interval_secs = 0);
+        assert!(err.contains("update_interval_secs"), "{err}");
+        let err = invalid(|config| {
+            config.base_backoff_secs = 10;
+            config.max_backoff_secs = 5;
+        });
+        assert!(err.contains("max_backoff_secs"), "{err}");
+        let err = invalid(|config| config.min_change_pct = Some(Decimal::NEGATIVE_ONE));
+        assert!(err.contains("min_change_pct"), "{err}");
+        let err = invalid(|config| config.flash_threshold_pct = Some(Decimal::ZERO));
+        assert!(err.contains("flash_threshold_pct"), "{err}");
+    }
+
+    #[test]
+    fn validate_checks_pairs_and_the_pairs_they_refer_to() {
+        let err = invalid(|config| config.trading_pairs = vec![PairConfig::new("BTCUSDT")]);
+        assert!(err.contains("BTCUSDT"), "{err}");
+        let err = invalid(|config| config.primary_pair = Some("ETH-USDT".to_string()));
+        assert!(err.contains("not in trading_pairs"), "{err}");
+        let err = invalid(|config| {
+            config.alerts.push(AlertRule {
+                pair: "BTC-USDT".to_string(),
+                above: None,
+                below: None,
+            })
+        });
+        assert!(err.contains("needs `above` or `below`"), "{err}");
+
+        let config = Config {
+            primary_pair: Some("btc-usdt".to_string()),
+            ..Config::default()
+        };
+        config.validate().unwrap();
+    }
+
+    /// The environment is shared by every test, so `merge_env` is covered by
+    /// this one test alone, which clears what it set before asserting.
+    #[test]
+    fn merge_env_overlays_and_validates() {
+        let merged = |vars: &[(&str, &str)]| {
+            for (name, value) in vars {
+                std::env::set_var(name, value);
+            }
+            let mut config = Config::default();
+            let result = config.merge_env().map(|()| config);
+            for (name, _) in vars {
+                std::env::remove_var(name);
+            }
+            result
+        };
+
+        let config = merged(&[
+            ("TICKER_TRADING_PAIRS", " ETH-USDT, ,SOL-USDT "),
+            ("TICKER_UPDATE_INTERVAL_SECS", "5"),
+            ("TICKER_SHOW_EXCHANGE", "Yes"),
+        ])
+        .unwrap();
+        let pairs: Vec<&str> = config
+            .trading_pairs
+            .iter()
+            .map(|p| p.pair.as_str())
+            .collect();
+        assert_eq!(pairs, ["ETH-USDT", "SOL-USDT"]);
+        assert_eq!(config.update_interval_secs, 5);
+        assert!(config.show_exchange);
+        assert_eq!(config.decimals, Config::default().decimals);
+
+        let err = merged(&[("TICKER_SHOW_EXCHANGE", "maybe")]).unwrap_err();
+        assert!(err.to_string().contains("TICKER_SHOW_EXCHANGE"), "{err}");
+        let err = merged(&[("TICKER_UPDATE_INTERVAL_SECS", "soon")]).unwrap_err();
+        assert!(err.to_string().contains("invalid value `soon`"), "{err}");
+        let err = merged(&[("TICKER_UPDATE_INTERVAL_SECS", "0")]).unwrap_err();
+        assert!(err.to_string().contains("update_interval_secs"), "{err}");
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn missing_icons_resolve_next_to_the_config() {
+        let dir = TempDir::new("icon-missing");
+        let config = dir.0.join("config.toml");
+        let exe = dir.0.join("bin/okk");
+        assert_eq!(
+            find_icon("icons/missing.png", &config, Some(&exe)),
+            dir.0.join("icons/missing.png")
+        );
+        assert_eq!(
+            find_icon("icons/missing.png", Path::new("config.toml"), None),
+            PathBuf::from("icons/missing.png")
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::sync::Arc;
+
+use exc::ExchangeError;
+
+/// Shared rather than boxed, so that errors can be cloned into state such as
+/// a pair's health.
+type SharedError = Arc<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub enum TickerError {
+    /// The configuration could not be read, parsed or failed validation.
+    ConfigError(String),
+    /// Talking to an exchange failed in a way that may clear up by itself,
+    /// e.g. a dropped connection or rate limiting.
+    ConnectionError(SharedError),
+    /// The exchange rejected the request for good, e.g. invalid credentials.
+    ExchangeError(SharedError),
+    /// The exchange does not list this trading pair.
+    InvalidSymbol(String),
+}
+
+impl TickerError {
+    /// Whether repeating the failed operation can succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TickerError::ConnectionError(_) => true,
+            TickerError::ConfigError(_)
+            | TickerError::ExchangeError(_)
+            | TickerError::InvalidSymbol(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for TickerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TickerError::ConfigError(msg) => write!(f, "configuration error: {msg}"),
+            TickerError::ConnectionError(msg) => write!(f, "connection error: {msg}"),
+            TickerError::ExchangeError(msg) => write!(f, "exchange error: {msg}"),
+            TickerError::InvalidSymbol(pair) => write!(f, "unknown trading pair `{pair}`"),
+        }
+    }
+}
+
+/// Errors are equal if they are the same variant with the same message; the
+/// wrapped errors can't be compared otherwise.
+impl PartialEq for TickerError {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for TickerError {}
+
+/// The message already includes the wrapped error, so the chain continues
+/// with what caused that.
+impl std::error::Error for TickerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TickerError::ConnectionError(err) | TickerError::ExchangeError(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Errors that no retry can fix become [`TickerError::ExchangeError`];
+/// everything else, including unclassified API errors, is treated as
+/// transient.
+impl From<ExchangeError> for TickerError {
+    fn from(err: ExchangeError) -> Self {
+        match err.flatten() {
+            err @ (ExchangeError::Instrument(_)
+            | ExchangeError::KeyError(_)
+            | ExchangeError::Forbidden(_)) => TickerError::ExchangeError(Arc::new(err)),
+            err => TickerError::ConnectionError(Arc::new(err)),
+        }
+    }
+}
+
+/// HTTP failures, from timeouts to unparsable bodies, are worth another try.
+impl From<reqwest::Error> for TickerError {
+    fn from(err: reqwest::Error) -> Self {
+        TickerError::ConnectionError(Arc::new(err))
+    }
+}
+
+/// Other failures, such as an unexpected REST response, are worth another
+/// try as well.
+impl From<anyhow::Error> for TickerError {
+    fn from(err: anyhow::Error) -> Self {
+        let err: Box<dyn std::error::Error + Send + Sync> = err.into();
+        TickerError::ConnectionError(err.into())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, TickerError>;
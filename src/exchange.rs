@@ -0,0 +1,2751 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use exc::core::types::Ticker;
+use exc::prelude::*;
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+
+use crate::config::{
+    self, BackoffStrategy, Config, InstrumentType, PairConfig, PriceSource, StreamMode,
+};
+use crate::error::TickerError;
+use crate::number_format;
+use crate::relay::{RelayClient, RelayUrl};
+
+/// Exchanges a trading pair can be monitored on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Exchange {
+    Okx,
+    Binance,
+}
+
+impl Exchange {
+    pub const ALL: [Exchange; 2] = [Exchange::Okx, Exchange::Binance];
+
+    /// Convert a `BASE-QUOTE` pair into the instrument name the exchange expects,
+    /// e.g. `BTC-USDT` on OKX but `btcusdt` on Binance.
+    pub fn instrument(&self, pair: &str) -> String {
+        match self {
+            Exchange::Okx => pair.to_uppercase(),
+            Exchange::Binance => pair.replace('-', "").to_lowercase(),
+        }
+    }
+}
+
+impl Exchange {
+    /// The exchange's spot trading page, with `{base}`/`{quote}` (and
+    /// `{BASE}`/`{QUOTE}` for upper case) standing for the pair's currencies.
+    #[cfg(feature = "tray")]
+    fn trade_url_template(&self) -> &'static str {
+        match self {
+            Exchange::Okx => "https://www.okx.com/trade-spot/{base}-{quote}",
+            Exchange::Binance => "https://www.binance.com/en/trade/{BASE}_{QUOTE}",
+        }
+    }
+
+    /// The page to trade `pair` on, such as
+    /// `https://www.okx.com/trade-spot/btc-usdt`.
+    #[cfg(feature = "tray")]
+    pub fn trade_url(&self, pair: &str) -> String {
+        match InstrumentType::of(pair) {
+            InstrumentType::Spot => {}
+            InstrumentType::Swap => {
+                return format!("https://www.okx.com/trade-swap/{}", pair.to_lowercase())
+            }
+            InstrumentType::Futures => {
+                return format!("https://www.okx.com/trade-futures/{}", pair.to_lowercase())
+            }
+        }
+        let (base, quote) = pair.split_once('-').unwrap_or((pair, ""));
+        self.trade_url_template()
+            .replace("{base}", &base.to_lowercase())
+            .replace("{quote}", &quote.to_lowercase())
+            .replace("{BASE}", &base.to_uppercase())
+            .replace("{QUOTE}", &quote.to_uppercase())
+    }
+
+    /// Fetch the price 24 hours ago, the 24h range and volume from the
+    /// exchange's public REST ticker. The streaming tickers in `exc` only
+    /// carry the last trade and book.
+    pub async fn fetch_stats_24h(
+        &self,
+        http: &reqwest::Client,
+        pair: &str,
+    ) -> anyhow::Result<Stats24h> {
+        // Field names of the open, high, low and volume.
+        let (ticker, fields) = match self {
+            Exchange::Okx => {
+                let body: serde_json::Value = http
+                    .get("https://www.okx.com/api/v5/market/ticker")
+                    .query(&[("instId", self.instrument(pair))])
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                (
+                    body["data"][0].clone(),
+                    ["open24h", "high24h", "low24h", "vol24h"],
+                )
+            }
+            Exchange::Binance => {
+                let body: serde_json::Value = http
+                    .get("https://api.binance.com/api/v3/ticker/24hr")
+                    .query(&[("symbol", self.instrument(pair).to_uppercase())])
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                (body, ["openPrice", "highPrice", "lowPrice", "volume"])
+            }
+        };
+        let [open, high, low, volume] =
+            fields.map(|field| ticker[field].as_str().and_then(|value| value.parse().ok()));
+        let funding_rate = match InstrumentType::of(pair) {
+            InstrumentType::Swap => self
+                .fetch_funding_rate(http, pair)
+                .await
+                .map_err(|err| tracing::warn!("failed to fetch the funding rate: {err}"))
+                .ok(),
+            _ => None,
+        };
+        Ok(Stats24h {
+            open: open.ok_or_else(|| anyhow::anyhow!("no 24h open price for {pair} on {self}"))?,
+            high,
+            low,
+            volume,
+            funding_rate,
+        })
+    }
+
+    /// The current funding rate of a perpetual swap, from OKX's public REST
+    /// API: `{"data": [{"fundingRate": "0.0001"}]}`.
+    async fn fetch_funding_rate(
+        &self,
+        http: &reqwest::Client,
+        pair: &str,
+    ) -> anyhow::Result<Decimal> {
+        let body: serde_json::Value = http
+            .get("https://www.okx.com/api/v5/public/funding-rate")
+            .query(&[("instId", self.instrument(pair))])
+            .send()
+            .await?
+            .json()
+            .await?;
+        body["data"][0]["fundingRate"]
+            .as_str()
+            .and_then(|rate| rate.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("no funding rate for {pair} on {self}"))
+    }
+
+    /// The pair's mark or index price from OKX's public REST API:
+    /// `{"data": [{"markPx": "65000.1"}]}` or `{"data": [{"idxPx": ...}]}`.
+    /// The index is that of the underlying, `BTC-USDT` for `BTC-USDT-SWAP`.
+    async fn fetch_reference_price(
+        &self,
+        http: &reqwest::Client,
+        pair: &str,
+        source: PriceSource,
+    ) -> anyhow::Result<Decimal> {
+        if *self != Exchange::Okx {
+            anyhow::bail!("{self} has no {source} price");
+        }
+        let inst = self.instrument(pair);
+        let (request, field) = match source {
+            PriceSource::Mark => {
+                // Spot pairs have a mark price for margin trading.
+                let inst_type = match InstrumentType::of(pair) {
+                    InstrumentType::Spot => "MARGIN",
+                    _ => okx_inst_type(pair),
+                };
+                let request = http
+                    .get("https://www.okx.com/api/v5/public/mark-price")
+                    .query(&[("instType", inst_type), ("instId", &inst)]);
+                (request, "markPx")
+            }
+            PriceSource::Index => {
+                let underlying = inst.splitn(3, '-').take(2).collect::<Vec<_>>().join("-");
+                let request = http
+                    .get("https://www.okx.com/api/v5/market/index-tickers")
+                    .query(&[("instId", underlying)]);
+                (request, "idxPx")
+            }
+            PriceSource::Last | PriceSource::Mid => {
+                anyhow::bail!("the {source} price comes from the ticker")
+            }
+        };
+        let body: serde_json::Value = request.send().await?.json().await?;
+        body["data"][0][field]
+            .as_str()
+            .and_then(|price| price.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("no {source} price for {pair} on {self}"))
+    }
+
+    /// Fetch the pair's current 1 minute candle from the exchange's public
+    /// REST API. `exc` can only stream tickers, so candles are polled.
+    pub async fn fetch_last_candle(
+        &self,
+        http: &reqwest::Client,
+        pair: &str,
+    ) -> anyhow::Result<CandleUpdate> {
+        let row = match self {
+            Exchange::Okx => {
+                let body: serde_json::Value = http
+                    .get("https://www.okx.com/api/v5/market/candles")
+                    .query(&[("instId", self.instrument(pair).as_str()), ("bar", "1m")])
+                    .query(&[("limit", 1)])
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                body["data"][0].clone()
+            }
+            Exchange::Binance => {
+                let body: serde_json::Value = http
+                    .get("https://api.binance.com/api/v3/klines")
+                    .query(&[
+                        ("symbol", self.instrument(pair).to_uppercase().as_str()),
+                        ("interval", "1m"),
+                    ])
+                    .query(&[("limit", 1)])
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                body[0].clone()
+            }
+        };
+        // Both send `[start, open, high, low, close, volume, ...]`, OKX with
+        // the start as a string too.
+        let start_ms = match &row[0] {
+            serde_json::Value::String(start) => start.parse().ok(),
+            start => start.as_i64(),
+        };
+        let [open, high, low, close, volume] = [1, 2, 3, 4, 5].map(|i| {
+            row[i]
+                .as_str()
+                .and_then(|value| value.parse::<Decimal>().ok())
+        });
+        let missing = || anyhow::anyhow!("no 1m candle for {pair} on {self}");
+        Ok(CandleUpdate {
+            start_ms: start_ms.ok_or_else(missing)?,
+            open: open.ok_or_else(missing)?,
+            high: high.ok_or_else(missing)?,
+            low: low.ok_or_else(missing)?,
+            close: close.ok_or_else(missing)?,
+            volume: volume.ok_or_else(missing)?,
+        })
+    }
+
+    /// Look the pair up with the exchange's public REST API. Fails with
+    /// [`TickerError::InvalidSymbol`] if the exchange doesn't list it, and with
+    /// a retryable [`TickerError::ConnectionError`] if the lookup itself fails.
+    pub async fn check_instrument(
+        &self,
+        http: &reqwest::Client,
+        pair: &str,
+    ) -> crate::error::Result<()> {
+        let request = match self {
+            Exchange::Okx => http
+                .get("https://www.okx.com/api/v5/public/instruments")
+                .query(&[
+                    ("instType", okx_inst_type(pair).to_string()),
+                    ("instId", self.instrument(pair)),
+                ]),
+            Exchange::Binance => http
+                .get("https://api.binance.com/api/v3/exchangeInfo")
+                .query(&[("symbol", self.instrument(pair).to_uppercase())]),
+        };
+        let body: serde_json::Value = request.send().await?.json().await?;
+        // OKX: "Instrument ID does not exist"; Binance: "Invalid symbol."
+        let unknown = match self {
+            Exchange::Okx => body["code"] == "51001",
+            Exchange::Binance => body["code"] == -1121,
+        };
+        if unknown {
+            return Err(TickerError::InvalidSymbol(pair.to_string()));
+        }
+        Ok(())
+    }
+
+    /// The pair's tick size, the smallest step its price moves in, from the
+    /// exchange's public REST API.
+    #[cfg(feature = "tray")]
+    pub async fn fetch_tick_size(
+        &self,
+        http: &reqwest::Client,
+        pair: &str,
+    ) -> anyhow::Result<Decimal> {
+        let tick_size = match self {
+            Exchange::Okx => {
+                let body: serde_json::Value = http
+                    .get("https://www.okx.com/api/v5/public/instruments")
+                    .query(&[
+                        ("instType", okx_inst_type(pair).to_string()),
+                        ("instId", self.instrument(pair)),
+                    ])
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                body["data"][0]["tickSz"].clone()
+            }
+            Exchange::Binance => {
+                let body: serde_json::Value = http
+                    .get("https://api.binance.com/api/v3/exchangeInfo")
+                    .query(&[("symbol", self.instrument(pair).to_uppercase())])
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                body["symbols"][0]["filters"]
+                    .as_array()
+                    .and_then(|filters| {
+                        filters
+                            .iter()
+                            .find(|filter| filter["filterType"] == "PRICE_FILTER")
+                    })
+                    .map_or(serde_json::Value::Null, |filter| filter["tickSize"].clone())
+            }
+        };
+        tick_size
+            .as_str()
+            .and_then(|tick_size| tick_size.parse::<Decimal>().ok())
+            .filter(|tick_size| *tick_size > Decimal::ZERO)
+            .ok_or_else(|| anyhow::anyhow!("no tick size for {pair} on {self}"))
+    }
+
+    /// The spot pairs the exchange currently trades, as `BASE-QUOTE`.
+    pub async fn fetch_pairs(&self, http: &reqwest::Client) -> anyhow::Result<Vec<String>> {
+        let pairs = match self {
+            Exchange::Okx => {
+                let body: serde_json::Value = http
+                    .get("https://www.okx.com/api/v5/public/instruments")
+                    .query(&[("instType", "SPOT")])
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                body["data"]
+                    .as_array()
+                    .ok_or_else(|| anyhow::anyhow!("no instruments in the response"))?
+                    .iter()
+                    .filter(|inst| inst["state"] == "live")
+                    .filter_map(|inst| Some(inst["instId"].as_str()?.to_string()))
+                    .collect()
+            }
+            Exchange::Binance => {
+                let body: serde_json::Value = http
+                    .get("https://api.binance.com/api/v3/exchangeInfo")
+                    .query(&[("permissions", "SPOT")])
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                body["symbols"]
+                    .as_array()
+                    .ok_or_else(|| anyhow::anyhow!("no symbols in the response"))?
+                    .iter()
+                    .filter(|symbol| symbol["status"] == "TRADING")
+                    .filter_map(|symbol| {
+                        let base = symbol["baseAsset"].as_str()?;
+                        let quote = symbol["quoteAsset"].as_str()?;
+                        Some(format!("{base}-{quote}"))
+                    })
+                    .collect()
+            }
+        };
+        Ok(pairs)
+    }
+}
+
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exchange::Okx => write!(f, "OKX"),
+            Exchange::Binance => write!(f, "Binance"),
+        }
+    }
+}
+
+impl FromStr for Exchange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "okx" => Ok(Exchange::Okx),
+            "binance" => Ok(Exchange::Binance),
+            other => Err(anyhow::anyhow!("unsupported exchange `{other}`")),
+        }
+    }
+}
+
+/// The OKX websocket hosts `exc` can connect to. It builds the URL itself, so
+/// other hosts such as a local mock server can't be used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OkxHost {
+    #[default]
+    Main,
+    Aws,
+    /// Demo trading.
+    Demo,
+}
+
+/// Where and how the OKX connector connects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OkxEndpoint {
+    host: OkxHost,
+    /// How long the websocket may be silent before it is pinged, and the
+    /// pong may take.
+    ping_timeout: Duration,
+}
+
+impl OkxHost {
+    /// Match a `ws://` or `wss://` URL against the supported hosts.
+    pub fn from_url(url: &str) -> anyhow::Result<Self> {
+        let parsed = reqwest::Url::parse(url)?;
+        if !matches!(parsed.scheme(), "ws" | "wss") {
+            anyhow::bail!("expected a ws:// or wss:// URL");
+        }
+        match parsed.host_str() {
+            Some("ws.okx.com") => Ok(OkxHost::Main),
+            Some("wsaws.okx.com") => Ok(OkxHost::Aws),
+            Some("wspap.okx.com") => Ok(OkxHost::Demo),
+            _ => anyhow::bail!(
+                "only ws.okx.com, wsaws.okx.com (AWS) and wspap.okx.com (demo trading) are supported"
+            ),
+        }
+    }
+}
+
+/// Parse a pair spec of the form `[exchange:]BASE-QUOTE`, e.g. `binance:ETH-USDT`.
+/// Pairs without an exchange prefix are monitored on OKX.
+pub fn parse_pair(spec: &str) -> anyhow::Result<(Exchange, String)> {
+    match spec.split_once(':') {
+        Some((exchange, pair)) => Ok((exchange.trim().parse()?, pair.trim().to_uppercase())),
+        None => Ok((Exchange::Okx, spec.trim().to_uppercase())),
+    }
+}
+
+/// OKX's `instType` of the instrument `pair` names.
+fn okx_inst_type(pair: &str) -> &'static str {
+    match InstrumentType::of(pair) {
+        InstrumentType::Spot => "SPOT",
+        InstrumentType::Swap => "SWAP",
+        InstrumentType::Futures => "FUTURES",
+    }
+}
+
+/// How often the 24h figures used for `change_pct_24h`, `high_24h`,
+/// `low_24h` and `volume_24h`, and a swap's funding rate, are refreshed.
+const STATS_24H_REFRESH: Duration = Duration::from_secs(5 * 60);
+
+/// A pair's rolling 24h figures from the exchange's REST ticker.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats24h {
+    pub open: Decimal,
+    pub high: Option<Decimal>,
+    pub low: Option<Decimal>,
+    /// Traded volume in the base currency.
+    pub volume: Option<Decimal>,
+    /// The current funding rate, for perpetual swaps.
+    pub funding_rate: Option<Decimal>,
+}
+
+/// The pair's 24h figures, fetched every [`STATS_24H_REFRESH`] in the
+/// background so that a slow lookup doesn't hold up the prices.
+struct Stats24hCache {
+    stats: watch::Receiver<Option<Stats24h>>,
+}
+
+impl Stats24hCache {
+    fn spawn(exchange: Exchange, http: &reqwest::Client, pair: &str) -> Self {
+        let (http, pair) = (http.clone(), pair.to_string());
+        let stats = refresh_in_background(STATS_24H_REFRESH, move || {
+            let (http, pair) = (http.clone(), pair.clone());
+            async move {
+                exchange
+                    .fetch_stats_24h(&http, &pair)
+                    .await
+                    .map_err(|err| tracing::warn!("failed to fetch 24h stats: {err}"))
+                    .ok()
+            }
+        });
+        Self { stats }
+    }
+
+    /// The latest figures, `None` until the first lookup succeeds.
+    fn get(&self) -> Option<Stats24h> {
+        *self.stats.borrow()
+    }
+}
+
+/// Run `fetch` now and every `period` after it completes in a task of its
+/// own, keeping the newest result in the returned channel. The task stops
+/// once the receiver is dropped, even in the middle of a lookup.
+fn refresh_in_background<T, F, Fut>(period: Duration, mut fetch: F) -> watch::Receiver<Option<T>>
+where
+    T: Send + Sync + 'static,
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = Option<T>> + Send,
+{
+    let (tx, rx) = watch::channel(None);
+    let refresh = async move {
+        loop {
+            tokio::select! {
+                value = fetch() => {
+                    if tx.send(value).is_err() {
+                        return;
+                    }
+                }
+                _ = tx.closed() => return,
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(period) => {}
+                _ = tx.closed() => return,
+            }
+        }
+    };
+    tokio::spawn(tracing::Instrument::in_current_span(refresh));
+    rx
+}
+
+/// How often a pair's mark or index price is looked up while it is the
+/// `price_source`.
+const REFERENCE_PRICE_REFRESH: Duration = Duration::from_secs(2);
+
+/// The pair's mark or index price, looked up every
+/// [`REFERENCE_PRICE_REFRESH`] in the background. `None` while the lookup
+/// fails.
+struct ReferencePriceCache {
+    price: watch::Receiver<Option<Decimal>>,
+}
+
+impl ReferencePriceCache {
+    /// A cache for `source` if it isn't part of the ticker.
+    fn spawn(
+        source: PriceSource,
+        exchange: Exchange,
+        http: &reqwest::Client,
+        pair: &str,
+    ) -> Option<Self> {
+        if !matches!(source, PriceSource::Mark | PriceSource::Index) {
+            return None;
+        }
+        let (http, pair) = (http.clone(), pair.to_string());
+        // Whether the last lookup failed, to warn only when it starts failing.
+        let failing = Arc::new(AtomicBool::new(false));
+        let price = refresh_in_background(REFERENCE_PRICE_REFRESH, move || {
+            let (http, pair, failing) = (http.clone(), pair.clone(), failing.clone());
+            async move {
+                let result = exchange.fetch_reference_price(&http, &pair, source).await;
+                let was_failing = failing.swap(result.is_err(), AtomicOrdering::Relaxed);
+                match result {
+                    Ok(price) => {
+                        if was_failing {
+                            tracing::info!("the {source} price is available again");
+                        }
+                        Some(price)
+                    }
+                    Err(err) => {
+                        // Only once, it may well stay unavailable for the pair.
+                        if !was_failing {
+                            tracing::warn!(
+                                "failed to look up the {source} price, showing the last price: {err}"
+                            );
+                        }
+                        None
+                    }
+                }
+            }
+        });
+        Some(Self { price })
+    }
+
+    fn get(&self) -> Option<Decimal> {
+        *self.price.borrow()
+    }
+}
+
+/// Halfway between the ticker's best bid and ask, if it has both.
+fn mid_price(ticker: &Ticker) -> Option<Decimal> {
+    match (ticker.bid, ticker.ask) {
+        (Some(bid), Some(ask)) if bid > Decimal::ZERO && ask > Decimal::ZERO => {
+            Some((bid + ask) / Decimal::TWO)
+        }
+        _ => None,
+    }
+}
+
+/// A 1 minute candle of a pair streamed with [`StreamMode::Candles`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandleUpdate {
+    /// Start of the candle, in milliseconds since the Unix epoch.
+    pub start_ms: i64,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    /// Traded volume in the base currency.
+    pub volume: Decimal,
+}
+
+/// A single price observed for a pair.
+/// Prices serialize as strings so they round-trip without loss.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceUpdate {
+    pub exchange: Exchange,
+    pub pair: String,
+    pub price: Decimal,
+    /// Percentage change over the last 24 hours, if the exchange provided it.
+    pub change_pct_24h: Option<Decimal>,
+    /// Best bid and ask at the time of the price, if the exchange sent them.
+    #[serde(default)]
+    pub bid: Option<Decimal>,
+    #[serde(default)]
+    pub ask: Option<Decimal>,
+    /// Highest and lowest price over the last 24 hours.
+    #[serde(default)]
+    pub high_24h: Option<Decimal>,
+    #[serde(default)]
+    pub low_24h: Option<Decimal>,
+    /// Traded volume over the last 24 hours in the base currency.
+    #[serde(default)]
+    pub volume_24h: Option<Decimal>,
+    /// The candle the price closes, for pairs streamed as candles.
+    #[serde(default)]
+    pub candle: Option<CandleUpdate>,
+    /// The current funding rate of a perpetual swap, as a fraction paid per
+    /// funding period, e.g. `0.0001`.
+    #[serde(default)]
+    pub funding_rate: Option<Decimal>,
+    /// When the price was received, in milliseconds since the Unix epoch.
+    pub timestamp_ms: i64,
+    /// When the exchange says the price was current, for tickers that carry
+    /// their event time.
+    #[serde(default)]
+    pub exchange_timestamp_ms: Option<i64>,
+}
+
+impl PriceUpdate {
+    pub fn new(exchange: Exchange, pair: impl Into<String>, price: Decimal) -> Self {
+        Self {
+            exchange,
+            pair: pair.into(),
+            price,
+            change_pct_24h: None,
+            bid: None,
+            ask: None,
+            high_24h: None,
+            low_24h: None,
+            volume_24h: None,
+            candle: None,
+            funding_rate: None,
+            timestamp_ms: Utc::now().timestamp_millis(),
+            exchange_timestamp_ms: None,
+        }
+    }
+
+    /// The update for a ticker from the stream: its last trade as the price,
+    /// kept exactly as sent, the best bid and ask, and the ticker's time.
+    pub fn from_ticker(exchange: Exchange, pair: impl Into<String>, ticker: &Ticker) -> Self {
+        let mut update =
+            Self::new(exchange, pair, ticker.last).with_bid_ask(ticker.bid, ticker.ask);
+        update.exchange_timestamp_ms = Some((ticker.ts.unix_timestamp_nanos() / 1_000_000) as i64);
+        update
+    }
+
+    /// How long the price took from the exchange to here, in milliseconds.
+    /// Clock skew between the two can make it negative.
+    pub fn latency_ms(&self) -> Option<i64> {
+        Some(self.timestamp_ms - self.exchange_timestamp_ms?)
+    }
+
+    /// Set `change_pct_24h` from the price 24 hours ago.
+    pub fn with_open_24h(mut self, open: Option<Decimal>) -> Self {
+        self.change_pct_24h = open
+            .filter(|open| !open.is_zero())
+            .map(|open| (self.price - open) / open * Decimal::ONE_HUNDRED);
+        self
+    }
+
+    pub fn with_volume_24h(mut self, volume: Option<Decimal>) -> Self {
+        self.volume_24h = volume;
+        self
+    }
+
+    pub fn with_range_24h(mut self, high: Option<Decimal>, low: Option<Decimal>) -> Self {
+        self.high_24h = high;
+        self.low_24h = low;
+        self
+    }
+
+    /// Set every 24h figure and the funding rate from `stats`.
+    pub fn with_stats_24h(self, stats: Option<Stats24h>) -> Self {
+        let mut update = self
+            .with_open_24h(stats.map(|stats| stats.open))
+            .with_volume_24h(stats.and_then(|stats| stats.volume))
+            .with_range_24h(
+                stats.and_then(|stats| stats.high),
+                stats.and_then(|stats| stats.low),
+            );
+        update.funding_rate = stats.and_then(|stats| stats.funding_rate);
+        update
+    }
+
+    pub fn with_candle(mut self, candle: CandleUpdate) -> Self {
+        self.candle = Some(candle);
+        self
+    }
+
+    pub fn with_bid_ask(mut self, bid: Option<Decimal>, ask: Option<Decimal>) -> Self {
+        self.bid = bid;
+        self.ask = ask;
+        self
+    }
+
+    /// The bid/ask spread as a percentage of the mid price, when both sides
+    /// are known.
+    #[cfg(feature = "tray")]
+    pub fn spread_pct(&self) -> Option<Decimal> {
+        let (bid, ask) = (self.bid?, self.ask?);
+        let mid = (bid + ask) / Decimal::TWO;
+        (!mid.is_zero()).then(|| (ask - bid) / mid * Decimal::ONE_HUNDRED)
+    }
+}
+
+/// State of a pair's ticker subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Subscribed and receiving tickers.
+    Connected,
+    /// The stream ended and is being re-established.
+    Reconnecting,
+    /// The last subscribe attempt failed; it is retried shortly.
+    Failed,
+    /// The exchange rejected the pair for good, or it failed too often; it is
+    /// no longer monitored.
+    Stopped,
+    /// The exchange doesn't list the pair; it is no longer monitored.
+    InvalidSymbol,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Reconnecting => write!(f, "reconnecting"),
+            ConnectionState::Failed => write!(f, "connection failed"),
+            ConnectionState::Stopped => write!(f, "stopped"),
+            ConnectionState::InvalidSymbol => write!(f, "unknown pair"),
+        }
+    }
+}
+
+/// How a monitored pair is doing, as last recorded by its task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairHealth {
+    pub state: ConnectionState,
+    /// Failed subscribe attempts since the last successful one.
+    pub consecutive_errors: u32,
+    /// When the pair's last price arrived, including repeated prices that
+    /// weren't forwarded.
+    pub last_update: Option<Instant>,
+    /// Reconnects since monitoring of the pair started, whether the stream
+    /// dropped or subscribing failed.
+    pub reconnects: u64,
+    /// The pair's most recent error. Kept after it recovers.
+    pub last_error: Option<TickerError>,
+    /// Prices sent to the consumers since monitoring of the pair started.
+    pub updates: u64,
+    /// Prices sent per second over the last [`THROUGHPUT_SUMMARY`], once one
+    /// has passed.
+    pub updates_per_sec: Option<Decimal>,
+    /// How long the last sent price took from arriving to being sent,
+    /// including the 24h stats lookup.
+    pub send_latency: Option<Duration>,
+    /// The longest of those since the last [`THROUGHPUT_SUMMARY`].
+    pub max_send_latency: Option<Duration>,
+}
+
+/// The health of every monitored pair, shared with the monitoring tasks.
+/// Pairs appear once their task reports and are removed when dropped from
+/// the config.
+pub type Health = Arc<Mutex<HashMap<(Exchange, String), PairHealth>>>;
+
+fn update_health(health: &Health, exchange: Exchange, pair: &str, f: impl FnOnce(&mut PairHealth)) {
+    let mut health = health
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(health
+        .entry((exchange, pair.to_string()))
+        .or_insert(PairHealth {
+            state: ConnectionState::Reconnecting,
+            consecutive_errors: 0,
+            last_update: None,
+            reconnects: 0,
+            last_error: None,
+            updates: 0,
+            updates_per_sec: None,
+            send_latency: None,
+            max_send_latency: None,
+        }));
+}
+
+/// Whether any pair received a price in the last `within`, forwarded or not.
+#[cfg(feature = "tray")]
+pub fn heard_within(health: &Health, within: Duration) -> bool {
+    health
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .values()
+        .filter_map(|health| health.last_update)
+        .any(|at| at.elapsed() <= within)
+}
+
+/// Record the pair's new state and tell the UI.
+fn report_state(
+    health: &Health,
+    status_tx: &StatusSender,
+    exchange: Exchange,
+    pair: &str,
+    state: ConnectionState,
+) {
+    update_health(health, exchange, pair, |health| health.state = state);
+    status_tx.send(ConnectionEvent {
+        exchange,
+        pair: pair.to_string(),
+        state,
+    });
+}
+
+/// Delivers [`ConnectionEvent`]s to the channel passed to
+/// [`ExchangeClient::start_price_monitoring`] and to the streams of
+/// [`MonitoringHandle::connection_events`].
+#[derive(Clone)]
+struct StatusSender {
+    channel: Sender<ConnectionEvent>,
+    subscribers: broadcast::Sender<ConnectionEvent>,
+}
+
+impl StatusSender {
+    fn new(channel: Sender<ConnectionEvent>) -> Self {
+        let (subscribers, _) = broadcast::channel(CONNECTION_EVENT_CAPACITY);
+        Self {
+            channel,
+            subscribers,
+        }
+    }
+
+    fn send(&self, event: ConnectionEvent) {
+        // The UI may have gone away already, and there may be no streams;
+        // prices going undelivered is what stops the task, so failed status
+        // sends are ignored.
+        let _ = self.subscribers.send(event.clone());
+        let _ = self.channel.send(event);
+    }
+}
+
+/// Events a [`MonitoringHandle::connection_events`] consumer may fall behind
+/// by before it skips the oldest.
+const CONNECTION_EVENT_CAPACITY: usize = 64;
+
+/// Send `update` to the consumers and note it in the pair's health. Returns
+/// false once every consumer is gone, which stops the pair's task; a slow one
+/// is handled by its own subscription.
+fn publish(
+    tx: &broadcast::Sender<PriceUpdate>,
+    health: &Health,
+    update: PriceUpdate,
+    received: Instant,
+) -> bool {
+    let (exchange, pair) = (update.exchange, update.pair.clone());
+    update_health(health, exchange, &pair, |health| {
+        health.last_update = Some(Instant::now())
+    });
+    if tx.send(update).is_err() {
+        tracing::warn!("no consumers left; stopping");
+        return false;
+    }
+    let latency = received.elapsed();
+    update_health(health, exchange, &pair, |health| {
+        health.updates += 1;
+        health.send_latency = Some(latency);
+        health.max_send_latency = health.max_send_latency.max(Some(latency));
+    });
+    true
+}
+
+fn record_error(health: &Health, exchange: Exchange, pair: &str, err: &TickerError) {
+    update_health(health, exchange, pair, |health| {
+        health.last_error = Some(err.clone())
+    });
+}
+
+/// Count a reconnect of the pair and return its total.
+fn count_reconnect(health: &Health, exchange: Exchange, pair: &str) -> u64 {
+    let mut reconnects = 0;
+    update_health(health, exchange, pair, |health| {
+        health.reconnects += 1;
+        reconnects = health.reconnects;
+    });
+    reconnects
+}
+
+/// How often each pair's throughput is logged and its rate updated.
+const THROUGHPUT_SUMMARY: Duration = Duration::from_secs(60);
+
+/// Log the prices the pair sent per second over the last `period`, at the
+/// start of which it had sent `counted` in total, and record the rate in its
+/// health. Returns the new total.
+fn log_throughput(
+    health: &Health,
+    exchange: Exchange,
+    pair: &str,
+    period: Duration,
+    counted: u64,
+) -> u64 {
+    let millis = period.as_millis().max(1) as u64;
+    let mut updates = counted;
+    update_health(health, exchange, pair, |health| {
+        updates = health.updates;
+        let rate = (Decimal::from(updates.saturating_sub(counted)) * Decimal::from(1000)
+            / Decimal::from(millis))
+        .round_dp(2);
+        match health.max_send_latency.take() {
+            Some(max) => tracing::info!("{rate} updates/s, send latency up to {max:?}"),
+            None => tracing::info!("no updates sent"),
+        }
+        health.updates_per_sec = Some(rate);
+    });
+    updates
+}
+
+/// How often the total reconnects per pair are logged.
+const RECONNECT_SUMMARY: Duration = Duration::from_secs(5 * 60);
+
+/// Log the reconnects of every pair each [`RECONNECT_SUMMARY`] until
+/// `shutdown` is signalled (or its sender dropped).
+async fn log_reconnects(health: Health, mut shutdown: watch::Receiver<bool>) {
+    let mut ticks = tokio::time::interval_at(
+        tokio::time::Instant::now() + RECONNECT_SUMMARY,
+        RECONNECT_SUMMARY,
+    );
+    loop {
+        tokio::select! {
+            _ = shutdown.changed() => return,
+            _ = ticks.tick() => {}
+        }
+        let mut counts: Vec<String> = health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(|((exchange, pair), health)| format!("{exchange} {pair}: {}", health.reconnects))
+            .collect();
+        if !counts.is_empty() {
+            counts.sort();
+            tracing::info!("reconnects so far: {}", counts.join(", "));
+        }
+    }
+}
+
+/// Sent whenever a pair's [`ConnectionState`] changes.
+#[derive(Debug, Clone)]
+pub struct ConnectionEvent {
+    pub exchange: Exchange,
+    pub pair: String,
+    pub state: ConnectionState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MonitoredPair {
+    exchange: Exchange,
+    pair: String,
+    interval: Duration,
+    stream: StreamMode,
+    /// Which of the ticker's prices is sent; candles always send the close.
+    price_source: PriceSource,
+    /// Made-up prices instead of the exchange's, see [`simulate_pair`].
+    simulated: bool,
+}
+
+impl MonitoredPair {
+    /// `pair_config` with the global settings of `config` it doesn't override.
+    fn new(config: &Config, pair_config: &PairConfig) -> anyhow::Result<Self> {
+        let (exchange, pair) = parse_pair(&pair_config.pair)?;
+        Ok(Self {
+            exchange,
+            pair,
+            interval: config.interval_for(pair_config),
+            stream: pair_config.stream,
+            price_source: config.price_source,
+            simulated: config.simulate,
+        })
+    }
+
+    fn key(&self) -> (Exchange, &str) {
+        (self.exchange, &self.pair)
+    }
+}
+
+pub struct ExchangeClient {
+    pairs: Vec<MonitoredPair>,
+    http: reqwest::Client,
+    backoff: Backoff,
+    subscribes: SubscribeLimiter,
+    skip_repeated_ticks: bool,
+    okx_endpoint: OkxEndpoint,
+    relay: Option<RelayClient>,
+}
+
+impl ExchangeClient {
+    /// A pair is identified by `(exchange, pair)`: the same pair may be listed
+    /// once per exchange, and repeated entries are monitored only once.
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let mut pairs: Vec<MonitoredPair> = Vec::new();
+        for pair_config in &config.trading_pairs {
+            let pair = MonitoredPair::new(config, pair_config)?;
+            if pairs.iter().any(|p| p.key() == pair.key()) {
+                tracing::warn!(
+                    "{} {} is listed more than once; ignoring duplicate",
+                    pair.exchange,
+                    pair.pair
+                );
+                continue;
+            }
+            pairs.push(pair);
+        }
+        let okx_host = match &config.ws_url {
+            Some(url) => OkxHost::from_url(url)?,
+            None => OkxHost::default(),
+        };
+        let relay = match &config.relay_url {
+            Some(url) => Some(RelayClient::new(RelayUrl::parse(url)?)),
+            None => None,
+        };
+        Ok(Self {
+            pairs,
+            http: http_client(config)?,
+            backoff: Backoff::from_config(config),
+            subscribes: SubscribeLimiter::new(config.subscribes_per_sec),
+            skip_repeated_ticks: config.skip_repeated_ticks,
+            okx_endpoint: OkxEndpoint {
+                host: okx_host,
+                ping_timeout: Duration::from_secs(config.ws_ping_timeout_secs),
+            },
+            relay,
+        })
+    }
+
+    /// The decimal places of each pair's tick size, looked up once at startup
+    /// so prices aren't shown more precisely than they move. Pairs whose
+    /// lookup fails or takes longer than [`TICK_SIZE_LOOKUP_TIMEOUT`] are left
+    /// out, so the tray isn't held up offline.
+    #[cfg(feature = "tray")]
+    pub async fn tick_decimals(&self) -> HashMap<(Exchange, String), u32> {
+        let lookups = self
+            .pairs
+            .iter()
+            .filter(|pair| !pair.simulated)
+            .map(|pair| async move {
+                let key = (pair.exchange, pair.pair.clone());
+                let lookup = pair.exchange.fetch_tick_size(&self.http, &pair.pair);
+                let result = tokio::time::timeout(TICK_SIZE_LOOKUP_TIMEOUT, lookup)
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+                match result {
+                    Ok(tick_size) => Some((key, tick_size.normalize().scale())),
+                    Err(err) => {
+                        tracing::warn!(
+                            "{} {}: failed to look up the tick size, using the default \
+                             precision: {err}",
+                            pair.exchange,
+                            pair.pair
+                        );
+                        None
+                    }
+                }
+            });
+        futures::future::join_all(lookups)
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Spawn one monitoring task per pair. Each exchange gets a single connector
+    /// that is shared by all of its pairs. Prices are sent on `tx` and changes
+    /// in a pair's connection on `status_tx`.
+    pub fn start_price_monitoring(
+        &self,
+        tx: broadcast::Sender<PriceUpdate>,
+        status_tx: Sender<ConnectionEvent>,
+    ) -> MonitoringHandle {
+        let health = Health::default();
+        let (summary, summary_rx) = watch::channel(false);
+        tokio::spawn(log_reconnects(health.clone(), summary_rx));
+        if let Some(relay) = &self.relay {
+            tracing::info!("streaming OKX tickers from the relay at {}", relay.url());
+        }
+        let mut handle = MonitoringHandle {
+            context: TaskContext {
+                tx,
+                status_tx: StatusSender::new(status_tx),
+                http: self.http.clone(),
+                backoff: self.backoff,
+                subscribes: self.subscribes.clone(),
+                reconnects: ReconnectGate::default(),
+                skip_repeated_ticks: self.skip_repeated_ticks,
+                health,
+            },
+            summary,
+            okx_endpoint: self.okx_endpoint,
+            relay: self.relay.clone(),
+            okx: None,
+            binance: None,
+            tasks: Vec::new(),
+            paused: None,
+        };
+        for pair in &self.pairs {
+            handle.spawn(pair.clone());
+        }
+        handle
+    }
+
+    /// Monitor the pairs like [`ExchangeClient::start_price_monitoring`], as
+    /// one stream of every pair's prices. Monitoring stops once the stream is
+    /// dropped; connection changes are only logged.
+    pub fn price_stream(&self) -> impl Stream<Item = PriceUpdate> {
+        let (tx, rx) = broadcast::channel(PRICE_STREAM_CAPACITY);
+        let (status_tx, _) = mpsc::channel();
+        let monitoring = self.start_price_monitoring(tx, status_tx);
+        receive_all(rx, monitoring, "price stream")
+    }
+}
+
+/// Everything sent on `rx` until it closes, holding on to `keep` until then.
+/// A consumer that falls behind skips what it missed with a warning.
+fn receive_all<T: Clone + Send + 'static, K: Send + 'static>(
+    rx: broadcast::Receiver<T>,
+    keep: K,
+    name: &'static str,
+) -> impl Stream<Item = T> {
+    futures::stream::unfold((rx, keep), move |(mut rx, keep)| async move {
+        loop {
+            match rx.recv().await {
+                Ok(item) => return Some((item, (rx, keep))),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("{name} fell behind; skipped {skipped} updates")
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// How long [`ExchangeClient::tick_decimals`] waits for each pair.
+#[cfg(feature = "tray")]
+const TICK_SIZE_LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Updates a [`ExchangeClient::price_stream`] consumer may fall behind by
+/// before it skips the oldest.
+const PRICE_STREAM_CAPACITY: usize = 64;
+
+/// Delays between reconnection attempts: `base` doubling with every
+/// consecutive failure up to `max`, randomized by the `strategy` so pairs
+/// that dropped together don't reconnect in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub strategy: BackoffStrategy,
+    /// Consecutive failures after which a pair is reported as failed rather
+    /// than reconnecting. It keeps retrying regardless.
+    pub failed_after: u32,
+    /// Reconnects of a pair in all after which it is given up on, if any.
+    pub max_retries: Option<u64>,
+}
+
+impl Backoff {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            base: Duration::from_secs(config.base_backoff_secs),
+            max: Duration::from_secs(config.max_backoff_secs),
+            strategy: config.backoff_strategy,
+            failed_after: config.max_consecutive_errors,
+            max_retries: config.max_total_retries,
+        }
+    }
+
+    /// Whether a pair that reconnected `reconnects` times should stop trying.
+    pub fn gives_up(&self, reconnects: u64) -> bool {
+        self.max_retries.is_some_and(|max| reconnects > max)
+    }
+
+    /// The delay before the next attempt after `failures` consecutive
+    /// failures, without jitter.
+    pub fn delay(&self, failures: u32) -> Duration {
+        self.base
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(self.max)
+    }
+
+    /// The delay before the next attempt after `failures` consecutive
+    /// failures, the last one having waited `prev`. Every task draws from its
+    /// own `rng` so pairs that failed together spread out.
+    pub fn next_delay(&self, failures: u32, prev: Duration, rng: &mut impl Rng) -> Duration {
+        let delay = self.delay(failures);
+        match self.strategy {
+            BackoffStrategy::Exponential => delay + jitter(delay, rng),
+            BackoffStrategy::FullJitter => delay.mul_f64(rng.gen_range(0.0..=1.0)),
+            BackoffStrategy::Decorrelated => {
+                let high = prev.saturating_mul(3).max(self.base);
+                rng.gen_range(self.base..=high).min(self.max)
+            }
+        }
+    }
+}
+
+fn jitter(delay: Duration, rng: &mut impl Rng) -> Duration {
+    delay.mul_f64(rng.gen_range(0.0..=0.5))
+}
+
+/// What every monitoring task gets a copy of.
+#[derive(Clone)]
+struct TaskContext {
+    tx: broadcast::Sender<PriceUpdate>,
+    status_tx: StatusSender,
+    /// For the REST lookups next to the ticker stream.
+    http: reqwest::Client,
+    backoff: Backoff,
+    subscribes: SubscribeLimiter,
+    reconnects: ReconnectGate,
+    /// Drop ticks with the same price as the previous one.
+    skip_repeated_ticks: bool,
+    health: Health,
+}
+
+/// Spaces out the ticker subscriptions to each exchange, shared by all
+/// pairs.
+#[derive(Clone)]
+struct SubscribeLimiter {
+    spacing: Duration,
+    /// When the next subscription to each exchange may be sent.
+    next: Arc<Mutex<HashMap<Exchange, tokio::time::Instant>>>,
+}
+
+impl SubscribeLimiter {
+    fn new(per_sec: u32) -> Self {
+        Self {
+            spacing: Duration::from_secs(1) / per_sec.max(1),
+            next: Arc::default(),
+        }
+    }
+
+    /// Wait for the next free slot to subscribe to `exchange`.
+    async fn wait(&self, exchange: Exchange) {
+        let slot = {
+            let mut next = self
+                .next
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let now = tokio::time::Instant::now();
+            let slot = next.get(&exchange).map_or(now, |&at| at.max(now));
+            next.insert(exchange, slot + self.spacing);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// One backoff shared by all pairs, so that when the network drops they
+/// don't each retry on their own schedule. A failed subscribe while the
+/// shared wait is running joins it rather than starting another; one by a
+/// pair that already waited in it starts the next, longer wait. Each pair's
+/// delay counts from the start of the wait. Any pair getting through ends
+/// the outage.
+#[derive(Clone, Default)]
+struct ReconnectGate {
+    outage: Arc<Mutex<Outage>>,
+}
+
+#[derive(Default)]
+struct Outage {
+    /// Failed waits in a row, as the `failures` of [`Backoff::delay`].
+    failures: u32,
+    /// When the current wait started.
+    since: Option<tokio::time::Instant>,
+}
+
+impl ReconnectGate {
+    /// Record a failed subscribe and return when the shared wait started and
+    /// the failures so far, to back off from together with the other pairs.
+    /// `waited` is the start of the wait the pair last backed off in.
+    fn failed(
+        &self,
+        backoff: &Backoff,
+        waited: Option<tokio::time::Instant>,
+    ) -> (tokio::time::Instant, u32) {
+        let mut outage = self
+            .outage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = tokio::time::Instant::now();
+        let since = match outage.since {
+            Some(since)
+                if since + backoff.delay(outage.failures) > now && waited != Some(since) =>
+            {
+                since
+            }
+            _ => {
+                outage.failures += 1;
+                outage.since = Some(now);
+                now
+            }
+        };
+        (since, outage.failures)
+    }
+
+    /// Record a successful subscribe, which resets the shared backoff.
+    fn connected(&self) {
+        let mut outage = self
+            .outage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *outage = Outage::default();
+    }
+}
+
+struct PairTask {
+    pair: MonitoredPair,
+    shutdown: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl PairTask {
+    async fn stop(self) {
+        let _ = self.shutdown.send(true);
+        if let Err(err) = self.handle.await {
+            tracing::error!("monitoring task failed: {err}");
+        }
+    }
+}
+
+/// Owns the monitoring tasks started by [`ExchangeClient::start_price_monitoring`].
+/// Dropping the handle also stops the tasks, without waiting for them.
+pub struct MonitoringHandle {
+    context: TaskContext,
+    okx_endpoint: OkxEndpoint,
+    /// One connector per exchange, cloned into every task. Clones share the
+    /// connector's websocket, which `exc` multiplexes the subscriptions of
+    /// all pairs over; when it drops, every pair resubscribes on the new one.
+    okx: Option<OkxClient>,
+    binance: Option<BinanceClient>,
+    /// Replaces `okx` for ticker streams when `relay_url` is set.
+    relay: Option<RelayClient>,
+    tasks: Vec<PairTask>,
+    /// The pairs to restart on [`MonitoringHandle::resume`] while paused.
+    paused: Option<Vec<MonitoredPair>>,
+    /// Stops the periodic reconnect summary.
+    summary: watch::Sender<bool>,
+}
+
+impl MonitoringHandle {
+    fn spawn(&mut self, pair: MonitoredPair) {
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let context = self.context.clone();
+        let make_task: TaskFactory = {
+            let (pair, context) = (pair.clone(), context.clone());
+            match (pair.stream, pair.exchange, &self.relay) {
+                _ if pair.simulated => Box::new(move || {
+                    let (pair, context) = (pair.clone(), context.clone());
+                    async move { simulate_pair(&pair, context).await }.boxed()
+                }),
+                (StreamMode::Candles, _, _) => Box::new(move || {
+                    let (pair, context) = (pair.clone(), context.clone());
+                    async move { poll_candles(&pair, context).await }.boxed()
+                }),
+                (StreamMode::Ticker, Exchange::Okx, Some(relay)) => {
+                    let client = relay.clone();
+                    Box::new(move || {
+                        let (client, pair, context) =
+                            (client.clone(), pair.clone(), context.clone());
+                        async move { stream_pair(client, &pair, context).await }.boxed()
+                    })
+                }
+                (StreamMode::Ticker, Exchange::Okx, None) => {
+                    let endpoint = self.okx_endpoint;
+                    let client = self
+                        .okx
+                        .get_or_insert_with(|| connect_okx(endpoint))
+                        .clone();
+                    Box::new(move || {
+                        let (client, pair, context) =
+                            (client.clone(), pair.clone(), context.clone());
+                        async move { stream_pair(client, &pair, context).await }.boxed()
+                    })
+                }
+                (StreamMode::Ticker, Exchange::Binance, _) => {
+                    let client = self.binance.get_or_insert_with(connect_binance).clone();
+                    Box::new(move || {
+                        let (client, pair, context) =
+                            (client.clone(), pair.clone(), context.clone());
+                        async move { stream_pair(client, &pair, context).await }.boxed()
+                    })
+                }
+            }
+        };
+        let handle = tokio::spawn(monitor_pair(pair.clone(), make_task, shutdown_rx, context));
+        self.tasks.push(PairTask {
+            pair,
+            shutdown,
+            handle,
+        });
+    }
+
+    /// Bring the running tasks in line with `config`: pairs that were removed or
+    /// whose settings changed are stopped, new ones are started.
+    #[cfg(feature = "tray")]
+    pub async fn reload(&mut self, config: &Config) -> anyhow::Result<()> {
+        let wanted = ExchangeClient::new(config)?.pairs;
+        if self.paused.is_some() {
+            self.paused = Some(wanted);
+            return Ok(());
+        }
+        let stale: Vec<MonitoredPair> = self
+            .tasks
+            .iter()
+            .filter(|task| !wanted.contains(&task.pair))
+            .map(|task| task.pair.clone())
+            .collect();
+        for pair in stale {
+            tracing::info!("{} {}: removed from config", pair.exchange, pair.pair);
+            self.remove(pair.exchange, &pair.pair).await;
+        }
+        for pair in wanted {
+            if !self.tasks.iter().any(|task| task.pair == pair) {
+                tracing::info!("{} {}: added to config", pair.exchange, pair.pair);
+                self.add(pair);
+            }
+        }
+        Ok(())
+    }
+
+    /// Start monitoring the pair of `pair_config`, with the settings of
+    /// `config` it doesn't override. Returns `false` if the pair is already
+    /// monitored, whatever its settings. While paused it starts on resume.
+    #[allow(dead_code)]
+    pub fn add_pair(&mut self, config: &Config, pair_config: &PairConfig) -> anyhow::Result<bool> {
+        Ok(self.add(MonitoredPair::new(config, pair_config)?))
+    }
+
+    /// Stop monitoring `pair`, written as in `trading_pairs`, and wait for its
+    /// task to exit. Returns `false` if it wasn't monitored.
+    #[allow(dead_code)]
+    pub async fn remove_pair(&mut self, pair: &str) -> anyhow::Result<bool> {
+        let (exchange, pair) = parse_pair(pair)?;
+        Ok(self.remove(exchange, &pair).await)
+    }
+
+    fn add(&mut self, pair: MonitoredPair) -> bool {
+        if let Some(paused) = self.paused.as_mut() {
+            if paused.iter().any(|p| p.key() == pair.key()) {
+                return false;
+            }
+            paused.push(pair);
+            return true;
+        }
+        if self.tasks.iter().any(|task| task.pair.key() == pair.key()) {
+            return false;
+        }
+        self.spawn(pair);
+        true
+    }
+
+    /// Stop the pair's task and forget its health.
+    async fn remove(&mut self, exchange: Exchange, pair: &str) -> bool {
+        if let Some(paused) = self.paused.as_mut() {
+            let before = paused.len();
+            paused.retain(|p| p.key() != (exchange, pair));
+            if paused.len() == before {
+                return false;
+            }
+        } else {
+            let Some(index) = self
+                .tasks
+                .iter()
+                .position(|task| task.pair.key() == (exchange, pair))
+            else {
+                return false;
+            };
+            self.tasks.remove(index).stop().await;
+        }
+        self.context
+            .health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&(exchange, pair.to_string()));
+        true
+    }
+
+    /// Stop every monitoring task and close the exchange connections until
+    /// [`MonitoringHandle::resume`] is called.
+    #[cfg(feature = "tray")]
+    pub async fn pause(&mut self) {
+        if self.paused.is_some() {
+            return;
+        }
+        let mut pairs = Vec::new();
+        for task in std::mem::take(&mut self.tasks) {
+            let pair = task.pair.clone();
+            task.stop().await;
+            update_health(&self.context.health, pair.exchange, &pair.pair, |health| {
+                health.state = ConnectionState::Stopped
+            });
+            pairs.push(pair);
+        }
+        // The tasks held the only other clones, so this drops the sockets.
+        self.okx = None;
+        self.binance = None;
+        self.paused = Some(pairs);
+        tracing::info!("monitoring paused");
+    }
+
+    /// Reconnect and restart the pairs stopped by [`MonitoringHandle::pause`].
+    #[cfg(feature = "tray")]
+    pub fn resume(&mut self) {
+        if let Some(pairs) = self.paused.take() {
+            tracing::info!("monitoring resumed");
+            for pair in pairs {
+                self.spawn(pair);
+            }
+        }
+    }
+
+    #[cfg(feature = "tray")]
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// The live health of the monitored pairs. Clone it to keep watching.
+    #[cfg(any(feature = "tray", test))]
+    pub fn health(&self) -> &Health {
+        &self.context.health
+    }
+
+    /// The [`ConnectionEvent`]s of every pair from now on, the same ones sent
+    /// on the channel given to [`ExchangeClient::start_price_monitoring`].
+    /// The stream ends once the handle is gone and every task has exited.
+    ///
+    /// Each pair's events arrive in the order its state changed; there is no
+    /// order between pairs. A pair's task reports `Connected` before it sends
+    /// the first price of the new subscription, and reports `Reconnecting`,
+    /// `Failed` or `Stopped` after the last price of the old one. Prices and
+    /// events travel on separate channels, though, so a consumer only sees
+    /// them in that order if it takes pending events before prices, e.g. with
+    /// a biased `select!`. Pairs added later are included; pausing doesn't
+    /// send events.
+    #[allow(dead_code)]
+    pub fn connection_events(&self) -> impl Stream<Item = ConnectionEvent> {
+        let rx = self.context.status_tx.subscribers.subscribe();
+        receive_all(rx, (), "connection event stream")
+    }
+
+    /// Signal every monitoring task to stop and wait until they have exited.
+    pub async fn shutdown(self) {
+        let _ = self.summary.send(true);
+        for task in &self.tasks {
+            let _ = task.shutdown.send(true);
+        }
+        for task in self.tasks {
+            if let Err(err) = task.handle.await {
+                tracing::error!("monitoring task failed: {err}");
+            }
+        }
+    }
+}
+
+/// How long a REST request may take to connect, and in all. Lookups that
+/// time out fail like any other and are retried.
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The client for REST lookups, going through the configured proxy if any,
+/// trusting the configured certificates and sending the `user_agent`. `exc`
+/// opens its websockets itself and supports none of them, so the ticker
+/// streams always connect directly and check against the public roots.
+pub fn http_client(config: &Config) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(config.user_agent())
+        .connect_timeout(HTTP_CONNECT_TIMEOUT)
+        .timeout(HTTP_TIMEOUT);
+    if let Some(url) = config.proxy_url() {
+        tracing::info!("using proxy {url} for REST requests; ticker streams connect directly");
+        builder = builder.proxy(reqwest::Proxy::all(&url)?);
+    }
+    if let Some(path) = &config.ca_cert_path {
+        let pem = std::fs::read(path)
+            .map_err(|err| anyhow::anyhow!("failed to read ca_cert_path {path}: {err}"))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|err| anyhow::anyhow!("invalid certificate in {path}: {err}"))?;
+        if certs.is_empty() {
+            anyhow::bail!("no certificates in {path}");
+        }
+        tracing::info!(
+            "trusting {} extra certificates for REST requests",
+            certs.len()
+        );
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if config.danger_accept_invalid_certs {
+        config::warn_always(
+            "danger_accept_invalid_certs is set: NOT checking certificates of REST requests; \
+             anyone on the network can intercept them",
+        );
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder.build()?)
+}
+
+type OkxClient = Exc<exc::okx::Okx, exc::okx::OkxRequest>;
+
+type BinanceClient = Exc<
+    exc::util::subscribe_tickers::TradeBidAsk<Exc<exc::binance::Binance, exc::binance::Request>>,
+    exc::core::types::SubscribeTickers,
+>;
+
+fn connect_okx(endpoint: OkxEndpoint) -> OkxClient {
+    Okx::endpoint()
+        .ws_ping_timeout(endpoint.ping_timeout)
+        .ws_connection_timeout(Duration::from_secs(2))
+        .aws(endpoint.host == OkxHost::Aws)
+        .testing(endpoint.host == OkxHost::Demo)
+        .connect_exc()
+}
+
+// Binance has no native ticker channel in `exc`, so tickers are assembled from
+// the trade and book streams.
+fn connect_binance() -> BinanceClient {
+    Binance::spot()
+        .ws_keep_alive_timeout(Duration::from_secs(30))
+        .connect_exc()
+        .into_subscribe_tickers()
+}
+
+/// Starts a pair's monitoring task, again for every restart.
+type TaskFactory = Box<dyn Fn() -> BoxFuture<'static, ()> + Send>;
+
+/// Times a pair's task is restarted after panicking before it is given up on.
+const MAX_RESTARTS: u32 = 5;
+
+/// Run the pair's task until `shutdown` is signalled (or its sender dropped),
+/// inside a `monitor` span naming the exchange and pair, so that its logs
+/// can be told apart and filtered with `RUST_LOG`. A task that panics is
+/// restarted after a backoff, up to [`MAX_RESTARTS`] times; one that
+/// returns is done. Meanwhile the pair's throughput is logged every
+/// [`THROUGHPUT_SUMMARY`].
+async fn monitor_pair(
+    pair: MonitoredPair,
+    make_task: TaskFactory,
+    mut shutdown: watch::Receiver<bool>,
+    context: TaskContext,
+) {
+    let span = tracing::info_span!("monitor", exchange = %pair.exchange, pair = %pair.pair);
+    // `Exchange::instrument` would shadow the method form.
+    let run = async move {
+        let mut restarts = 0;
+        let mut throughput = tokio::time::interval_at(
+            tokio::time::Instant::now() + THROUGHPUT_SUMMARY,
+            THROUGHPUT_SUMMARY,
+        );
+        let (mut counted, mut counted_at) = (0, tokio::time::Instant::now());
+        loop {
+            let mut task = tokio::spawn(tracing::Instrument::in_current_span(make_task()));
+            let result = loop {
+                tokio::select! {
+                    _ = shutdown.changed() => {
+                        // Wait for it to drop its sockets and senders, so
+                        // that shutting down waits for them too.
+                        task.abort();
+                        let _ = task.await;
+                        tracing::info!("monitoring stopped");
+                        return;
+                    }
+                    at = throughput.tick() => {
+                        let (exchange, period) = (pair.exchange, at - counted_at);
+                        counted =
+                            log_throughput(&context.health, exchange, &pair.pair, period, counted);
+                        counted_at = at;
+                    }
+                    result = &mut task => break result,
+                }
+            };
+            let panic = match result {
+                Ok(()) => return,
+                Err(err) => match err.try_into_panic() {
+                    Ok(panic) => panic_message(panic),
+                    Err(_) => return,
+                },
+            };
+            if restarts == MAX_RESTARTS {
+                tracing::error!("task panicked: {panic}; giving up after {restarts} restarts");
+                report_state(
+                    &context.health,
+                    &context.status_tx,
+                    pair.exchange,
+                    &pair.pair,
+                    ConnectionState::Stopped,
+                );
+                return;
+            }
+            restarts += 1;
+            let delay = context.backoff.delay(restarts);
+            tracing::error!(
+                "task panicked: {panic}; restarting in {delay:?} ({restarts}/{MAX_RESTARTS})"
+            );
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    tracing::info!("monitoring stopped");
+                    return;
+                }
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    };
+    tracing::Instrument::instrument(run, span).await
+}
+
+/// The message a task panicked with, if it was a string.
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "(no message)".to_string(),
+        },
+    }
+}
+
+/// Forwards at most one price per `interval`; ticks in between are dropped.
+/// Stops once nobody is subscribed to `tx` any more.
+async fn stream_pair<S>(mut client: S, pair: &MonitoredPair, context: TaskContext)
+where
+    S: SubscribeTickersService,
+{
+    let MonitoredPair {
+        exchange,
+        pair,
+        interval,
+        price_source,
+        ..
+    } = pair;
+    let (exchange, interval) = (*exchange, *interval);
+    let TaskContext {
+        tx,
+        status_tx,
+        http,
+        backoff,
+        subscribes,
+        reconnects,
+        skip_repeated_ticks,
+        health,
+    } = context;
+    let report = |state| report_state(&health, &status_tx, exchange, pair, state);
+    let inst = exchange.instrument(pair);
+    let stats_24h = Stats24hCache::spawn(exchange, &http, pair);
+    let reference_price = ReferencePriceCache::spawn(*price_source, exchange, &http, pair);
+    let mut last_sent: Option<Instant> = None;
+    let mut last_price: Option<Decimal> = None;
+    let mut failures = 0;
+    let mut delay = Duration::ZERO;
+    let mut waited = None;
+    let mut rng = StdRng::from_entropy();
+    let give_up = |err: TickerError| {
+        tracing::error!("{err}; giving up");
+        record_error(&health, exchange, pair, &err);
+        report(match err {
+            TickerError::InvalidSymbol(_) => ConnectionState::InvalidSymbol,
+            _ => ConnectionState::Stopped,
+        });
+    };
+    let give_up_retrying = |reconnects: u64| {
+        tracing::error!("reconnected {reconnects} times; giving up");
+        report(ConnectionState::Stopped);
+    };
+    // A mistyped pair would otherwise be retried forever, as OKX doesn't
+    // reject unknown instruments on the websocket in a way `exc` reports.
+    if let Err(err) = exchange.check_instrument(&http, pair).await {
+        if !err.is_retryable() {
+            return give_up(err);
+        }
+        tracing::warn!("could not look up the pair: {err}");
+    }
+    loop {
+        subscribes.wait(exchange).await;
+        tracing::info!("subscribing to {inst}");
+        match client.subscribe_tickers(&inst).await {
+            Ok(mut stream) => {
+                failures = 0;
+                delay = Duration::ZERO;
+                reconnects.connected();
+                update_health(&health, exchange, pair, |health| {
+                    health.consecutive_errors = 0
+                });
+                report(ConnectionState::Connected);
+                while let Some(c) = stream.next().await {
+                    match c {
+                        Ok(c) => {
+                            let received = Instant::now();
+                            update_health(&health, exchange, pair, |health| {
+                                health.last_update = Some(received)
+                            });
+                            let price = match price_source {
+                                PriceSource::Mid => mid_price(&c).unwrap_or(c.last),
+                                _ => c.last,
+                            };
+                            if skip_repeated_ticks && last_price == Some(price) {
+                                continue;
+                            }
+                            let mut update = PriceUpdate::from_ticker(exchange, pair, &c);
+                            update.price = price;
+                            match update.latency_ms() {
+                                Some(latency) => {
+                                    tracing::info!("last {} ({latency} ms old)", c.last)
+                                }
+                                None => tracing::info!("last {}", c.last),
+                            }
+                            if last_sent.is_some_and(|at| at.elapsed() < interval) {
+                                continue;
+                            }
+                            // Only what was sent counts as repeated; a change
+                            // dropped by the throttle goes out on its next tick.
+                            last_sent = Some(Instant::now());
+                            last_price = Some(price);
+                            if let Some(price) = reference_price.as_ref().and_then(|r| r.get()) {
+                                update.price = price;
+                            }
+                            let update = update.with_stats_24h(stats_24h.get());
+                            if !publish(&tx, &health, update, received) {
+                                return;
+                            }
+                        }
+                        Err(err) => {
+                            tracing::error!("{err}");
+                            record_error(&health, exchange, pair, &err.into());
+                        }
+                    }
+                }
+                let reconnects = count_reconnect(&health, exchange, pair);
+                if backoff.gives_up(reconnects) {
+                    return give_up_retrying(reconnects);
+                }
+                tracing::warn!("stream is dead; reconnecting (#{reconnects})..");
+                report(ConnectionState::Reconnecting);
+                delay = backoff.next_delay(failures, delay, &mut rng);
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => {
+                let err = match err.flatten() {
+                    ExchangeError::Instrument(_) => TickerError::InvalidSymbol(pair.clone()),
+                    err => TickerError::from(err),
+                };
+                if !err.is_retryable() {
+                    return give_up(err);
+                }
+                record_error(&health, exchange, pair, &err);
+                let count = count_reconnect(&health, exchange, pair);
+                if backoff.gives_up(count) {
+                    tracing::error!("request error: {err}");
+                    return give_up_retrying(count);
+                }
+                tracing::error!("request error: {err}; retrying (#{count})..");
+                failures += 1;
+                update_health(&health, exchange, pair, |health| {
+                    health.consecutive_errors = failures
+                });
+                report(if failures >= backoff.failed_after {
+                    ConnectionState::Failed
+                } else {
+                    ConnectionState::Reconnecting
+                });
+                // Back off together with the other failing pairs, each drawing
+                // its own delay so they don't all subscribe at once.
+                let (since, shared_failures) = reconnects.failed(&backoff, waited);
+                waited = Some(since);
+                delay = backoff.next_delay(shared_failures, delay, &mut rng);
+                tokio::time::sleep_until(since + delay).await;
+            }
+        }
+    }
+}
+
+/// The shortest time between two candle requests of a pair, to stay clear
+/// of the exchanges' REST rate limits.
+const MIN_CANDLE_POLL: Duration = Duration::from_secs(5);
+
+/// Polls the pair's current 1 minute candle every `interval`, but no more
+/// often than [`MIN_CANDLE_POLL`], and forwards its close as the price.
+/// Stops once nobody is subscribed to `tx` any more.
+async fn poll_candles(pair: &MonitoredPair, context: TaskContext) {
+    let MonitoredPair {
+        exchange,
+        pair,
+        interval,
+        ..
+    } = pair;
+    let (exchange, poll) = (*exchange, (*interval).max(MIN_CANDLE_POLL));
+    let TaskContext {
+        tx,
+        status_tx,
+        http,
+        backoff,
+        health,
+        ..
+    } = context;
+    let report = |state| report_state(&health, &status_tx, exchange, pair, state);
+    let stats_24h = Stats24hCache::spawn(exchange, &http, pair);
+    // `None` until the first attempt, so that it is reported either way.
+    let mut failures = None;
+    let mut delay = Duration::ZERO;
+    let mut rng = StdRng::from_entropy();
+    loop {
+        match exchange.fetch_last_candle(&http, pair).await {
+            Ok(candle) => {
+                let received = Instant::now();
+                delay = Duration::ZERO;
+                if failures != Some(0) {
+                    failures = Some(0);
+                    update_health(&health, exchange, pair, |health| {
+                        health.consecutive_errors = 0
+                    });
+                    report(ConnectionState::Connected);
+                }
+                tracing::info!("1m candle closing at {}", candle.close);
+                let update = PriceUpdate::new(exchange, pair, candle.close)
+                    .with_stats_24h(stats_24h.get())
+                    .with_candle(candle);
+                if !publish(&tx, &health, update, received) {
+                    return;
+                }
+                tokio::time::sleep(poll).await;
+            }
+            Err(err) => {
+                let failed = failures.unwrap_or(0) + 1;
+                failures = Some(failed);
+                let err = TickerError::from(err);
+                record_error(&health, exchange, pair, &err);
+                let count = count_reconnect(&health, exchange, pair);
+                if backoff.gives_up(count) {
+                    tracing::error!("failed to fetch the 1m candle: {err}; giving up");
+                    report(ConnectionState::Stopped);
+                    return;
+                }
+                tracing::error!("failed to fetch the 1m candle: {err}; retrying (#{count})..");
+                update_health(&health, exchange, pair, |health| {
+                    health.consecutive_errors = failed
+                });
+                report(if failed >= backoff.failed_after {
+                    ConnectionState::Failed
+                } else {
+                    ConnectionState::Reconnecting
+                });
+                delay = backoff.next_delay(failed, delay, &mut rng);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Where a simulated pair starts, by base currency; others start at 1.
+const SIMULATED_START: [(&str, i64, u32); 5] = [
+    ("BTC", 6_500_000, 2),
+    ("ETH", 350_000, 2),
+    ("SOL", 15_000, 2),
+    ("DOGE", 15, 2),
+    ("SHIB", 2_500, 8),
+];
+
+/// Forwards a random walk for the pair every `interval`, moving at most
+/// 0.1% a step, without any network access. The walk is seeded from the
+/// pair, so every run shows the same prices.
+async fn simulate_pair(pair: &MonitoredPair, context: TaskContext) {
+    let MonitoredPair {
+        exchange,
+        pair,
+        interval,
+        ..
+    } = pair;
+    let exchange = *exchange;
+    let TaskContext {
+        tx,
+        status_tx,
+        health,
+        ..
+    } = context;
+    let base = pair.split('-').next().unwrap_or_default();
+    let open = SIMULATED_START
+        .iter()
+        .find(|(symbol, ..)| *symbol == base)
+        .map_or(Decimal::ONE, |&(_, num, scale)| Decimal::new(num, scale));
+    let seed = format!("{exchange} {pair}")
+        .bytes()
+        .fold(0u64, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte.into())
+        });
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut price = open;
+    report_state(
+        &health,
+        &status_tx,
+        exchange,
+        pair,
+        ConnectionState::Connected,
+    );
+    loop {
+        let step = Decimal::new(rng.gen_range(-10..=10), 4);
+        price *= Decimal::ONE + step;
+        price = price.round_dp(number_format::decimals_for(price, open.scale()));
+        let update = PriceUpdate::new(exchange, pair, price).with_open_24h(Some(open));
+        if !publish(&tx, &health, update, Instant::now()) {
+            return;
+        }
+        tokio::time::sleep(*interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use std::sync::mpsc::Receiver;
+
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let backoff = Backoff {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            strategy: BackoffStrategy::Exponential,
+            failed_after: 5,
+            max_retries: None,
+        };
+        let delays: Vec<u64> = (0..7).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10, 10]);
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(10));
+        for failures in 0..40 {
+            let delay = backoff.next_delay(failures, Duration::ZERO, &mut rand::thread_rng());
+            assert!(delay >= backoff.delay(failures));
+            assert!(delay <= backoff.delay(failures).mul_f64(1.5));
+        }
+    }
+
+    #[test]
+    fn full_jitter_stays_under_the_exponential_delay() {
+        let backoff = Backoff {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            strategy: BackoffStrategy::FullJitter,
+            failed_after: 5,
+            max_retries: None,
+        };
+        let mut rng = rand::thread_rng();
+        for failures in 0..40 {
+            let delays: Vec<Duration> = (0..100)
+                .map(|_| backoff.next_delay(failures, Duration::ZERO, &mut rng))
+                .collect();
+            assert!(delays.iter().all(|delay| *delay <= backoff.delay(failures)));
+            assert!(delays
+                .iter()
+                .any(|delay| *delay < backoff.delay(failures) / 2));
+        }
+    }
+
+    #[test]
+    fn decorrelated_stays_between_the_base_and_three_times_the_previous() {
+        let backoff = Backoff {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            strategy: BackoffStrategy::Decorrelated,
+            failed_after: 5,
+            max_retries: None,
+        };
+        let mut rng = rand::thread_rng();
+        let mut delay = Duration::ZERO;
+        for failures in 0..1000 {
+            let prev = delay;
+            delay = backoff.next_delay(failures, prev, &mut rng);
+            assert!(delay >= backoff.base);
+            assert!(delay <= (prev * 3).max(backoff.base).min(backoff.max));
+        }
+        // It gets up to the cap, not just around the base.
+        assert!((0..100).any(|_| backoff.next_delay(0, backoff.max, &mut rng) > backoff.max / 2));
+    }
+
+    #[test]
+    fn jitter_is_spread_and_independent_per_task() {
+        let delay = Duration::from_secs(10);
+        let samples: Vec<Duration> = (0..1000)
+            .map(|_| jitter(delay, &mut rand::thread_rng()))
+            .collect();
+        assert!(samples.iter().all(|j| *j <= delay / 2));
+        assert!(samples.iter().any(|j| *j < delay / 10));
+        assert!(samples.iter().any(|j| *j > delay * 4 / 10));
+
+        // Two tasks reconnecting in the same millisecond draw different
+        // jitter.
+        let (mut a, mut b) = (StdRng::from_entropy(), StdRng::from_entropy());
+        let a: Vec<Duration> = (0..8).map(|_| jitter(delay, &mut a)).collect();
+        let b: Vec<Duration> = (0..8).map(|_| jitter(delay, &mut b)).collect();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn price_update_round_trips_through_json() {
+        let update = PriceUpdate {
+            exchange: Exchange::Binance,
+            pair: "BTC-USDT".to_string(),
+            price: "65000.10".parse().unwrap(),
+            change_pct_24h: Some("-2.35".parse().unwrap()),
+            bid: Some("65000.00".parse().unwrap()),
+            ask: None,
+            high_24h: None,
+            low_24h: None,
+            volume_24h: None,
+            candle: None,
+            funding_rate: None,
+            timestamp_ms: 1_700_000_000_123,
+            exchange_timestamp_ms: Some(1_700_000_000_100),
+        };
+
+        let json = serde_json::to_string(&update).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["exchange"], "binance");
+        assert_eq!(value["price"], "65000.10");
+        assert_eq!(value["change_pct_24h"], "-2.35");
+        assert_eq!(value["bid"], "65000.00");
+        assert!(value["ask"].is_null());
+        assert_eq!(value["timestamp_ms"], 1_700_000_000_123i64);
+        assert_eq!(value["exchange_timestamp_ms"], 1_700_000_000_100i64);
+
+        let back: PriceUpdate = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, update);
+    }
+
+    #[test]
+    fn okx_swaps_and_futures_are_looked_up_as_such() {
+        assert_eq!(okx_inst_type("BTC-USDT"), "SPOT");
+        assert_eq!(okx_inst_type("BTC-USDT-SWAP"), "SWAP");
+        assert_eq!(okx_inst_type("BTC-USD-250328"), "FUTURES");
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn okx_swaps_and_futures_are_traded_as_such() {
+        assert_eq!(
+            Exchange::Okx.trade_url("BTC-USDT-SWAP"),
+            "https://www.okx.com/trade-swap/btc-usdt-swap"
+        );
+        assert_eq!(
+            Exchange::Okx.trade_url("BTC-USDT"),
+            "https://www.okx.com/trade-spot/btc-usdt"
+        );
+    }
+
+    use std::collections::VecDeque;
+    use std::task::{Context, Poll};
+
+    use exc::core::types::{SubscribeTickers, TickerStream};
+
+    #[test]
+    fn ticker_price_is_copied_verbatim() {
+        let ticker = ticker("65000.1000").unwrap();
+        let update = PriceUpdate::from_ticker(Exchange::Okx, "BTC-USDT", &ticker);
+        assert_eq!(update.price, ticker.last);
+        assert_eq!(update.price.to_string(), "65000.1000");
+    }
+
+    #[test]
+    fn ticker_update_is_stamped_when_received() {
+        let before = Utc::now().timestamp_millis();
+        let update = PriceUpdate::from_ticker(Exchange::Okx, "BTC-USDT", &ticker("1").unwrap());
+        let after = Utc::now().timestamp_millis();
+        assert!((before..=after).contains(&update.timestamp_ms));
+    }
+
+    #[test]
+    fn ticker_update_keeps_the_exchange_time() {
+        let mut ticker = ticker("1").unwrap();
+        ticker.ts = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let mut update = PriceUpdate::from_ticker(Exchange::Okx, "BTC-USDT", &ticker);
+        assert_eq!(update.exchange_timestamp_ms, Some(1_700_000_000_000));
+        update.timestamp_ms = 1_700_000_000_250;
+        assert_eq!(update.latency_ms(), Some(250));
+    }
+
+    #[test]
+    fn mid_price_needs_both_sides_of_the_book() {
+        let mut ticker = ticker("100").unwrap();
+        assert_eq!(mid_price(&ticker), None);
+        ticker.bid = Some("99.5".parse().unwrap());
+        assert_eq!(mid_price(&ticker), None);
+        ticker.ask = Some("100.7".parse().unwrap());
+        assert_eq!(mid_price(&ticker), Some("100.1".parse().unwrap()));
+    }
+
+    /// What the mock answers to a subscription.
+    enum Script {
+        /// Subscribing succeeds and the stream yields these, then stays open.
+        Stream(Vec<Result<Ticker, ExchangeError>>),
+        /// Like `Stream`, waiting the given time before each item.
+        Paced(Vec<(Duration, Result<Ticker, ExchangeError>)>),
+        /// Like `Stream`, but the stream ends after them.
+        Ends(Vec<Result<Ticker, ExchangeError>>),
+        /// Subscribing fails.
+        Fail(ExchangeError),
+    }
+
+    /// An exchange answering subscriptions from a script. Once the script
+    /// runs out it subscribes to a stream that never yields.
+    #[derive(Clone, Default)]
+    struct MockExchange {
+        script: Arc<Mutex<VecDeque<Script>>>,
+        subscribes: Arc<Mutex<usize>>,
+    }
+
+    impl MockExchange {
+        fn new(script: impl IntoIterator<Item = Script>) -> Self {
+            Self {
+                script: Arc::new(Mutex::new(script.into_iter().collect())),
+                ..Self::default()
+            }
+        }
+
+        fn subscribes(&self) -> usize {
+            *self.subscribes.lock().unwrap()
+        }
+    }
+
+    impl tower::Service<SubscribeTickers> for MockExchange {
+        type Response = TickerStream;
+        type Error = ExchangeError;
+        type Future = futures::future::Ready<Result<TickerStream, ExchangeError>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ExchangeError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: SubscribeTickers) -> Self::Future {
+            *self.subscribes.lock().unwrap() += 1;
+            futures::future::ready(match self.script.lock().unwrap().pop_front() {
+                Some(Script::Stream(items)) => Ok(futures::stream::iter(items)
+                    .chain(futures::stream::pending())
+                    .boxed()),
+                Some(Script::Paced(items)) => Ok(futures::stream::iter(items)
+                    .then(|(wait, item)| async move {
+                        tokio::time::sleep(wait).await;
+                        item
+                    })
+                    .chain(futures::stream::pending())
+                    .boxed()),
+                Some(Script::Ends(items)) => Ok(futures::stream::iter(items).boxed()),
+                Some(Script::Fail(err)) => Err(err),
+                None => Ok(futures::stream::pending().boxed()),
+            })
+        }
+    }
+
+    fn ticker(last: &str) -> Result<Ticker, ExchangeError> {
+        Ok(Ticker {
+            ts: time::OffsetDateTime::now_utc(),
+            last: last.parse().unwrap(),
+            size: Decimal::ONE,
+            buy: None,
+            bid: None,
+            bid_size: None,
+            ask: None,
+            ask_size: None,
+        })
+    }
+
+    fn unavailable() -> ExchangeError {
+        ExchangeError::Unavailable(anyhow::anyhow!("connection reset"))
+    }
+
+    /// A running [`stream_pair`] on a mock exchange and what it sends.
+    struct Harness {
+        prices: broadcast::Receiver<PriceUpdate>,
+        statuses: Receiver<ConnectionEvent>,
+        health: Health,
+        _shutdown: watch::Sender<bool>,
+    }
+
+    impl Harness {
+        fn start(exchange: &MockExchange, backoff: Backoff) -> Self {
+            let exchange = exchange.clone();
+            Self::run(backoff, move |pair, context| {
+                let (client, pair, context) = (exchange.clone(), pair.clone(), context.clone());
+                async move { stream_pair(client, &pair, context).await }.boxed()
+            })
+        }
+
+        /// Like [`Harness::start`], sending at most one price per `interval`.
+        fn start_throttled(exchange: &MockExchange, interval: Duration) -> Self {
+            let exchange = exchange.clone();
+            Self::spawn(NO_WAIT, interval, move |pair, context| {
+                let (client, pair, context) = (exchange.clone(), pair.clone(), context.clone());
+                async move { stream_pair(client, &pair, context).await }.boxed()
+            })
+        }
+
+        /// Run `task` for the pair under [`monitor_pair`].
+        fn run(
+            backoff: Backoff,
+            task: impl Fn(&MonitoredPair, &TaskContext) -> BoxFuture<'static, ()> + Send + 'static,
+        ) -> Self {
+            Self::spawn(backoff, Duration::ZERO, task)
+        }
+
+        fn spawn(
+            backoff: Backoff,
+            interval: Duration,
+            task: impl Fn(&MonitoredPair, &TaskContext) -> BoxFuture<'static, ()> + Send + 'static,
+        ) -> Self {
+            let (tx, prices) = broadcast::channel(16);
+            let (status_tx, statuses) = mpsc::channel();
+            let health = Health::default();
+            // The REST lookups go to a closed port, so they fail right away
+            // and the pair is streamed regardless.
+            let http = reqwest::Client::builder()
+                .proxy(reqwest::Proxy::all("http://127.0.0.1:9").unwrap())
+                .build()
+                .unwrap();
+            let context = TaskContext {
+                tx,
+                status_tx: StatusSender::new(status_tx),
+                http,
+                backoff,
+                subscribes: SubscribeLimiter::new(1000),
+                reconnects: ReconnectGate::default(),
+                skip_repeated_ticks: true,
+                health: health.clone(),
+            };
+            let pair = MonitoredPair {
+                exchange: Exchange::Okx,
+                pair: "BTC-USDT".to_string(),
+                interval,
+                stream: StreamMode::Ticker,
+                price_source: PriceSource::Last,
+                simulated: false,
+            };
+            let (shutdown, shutdown_rx) = watch::channel(false);
+            let make_task: TaskFactory = {
+                let (pair, context) = (pair.clone(), context.clone());
+                Box::new(move || task(&pair, &context))
+            };
+            tokio::spawn(monitor_pair(pair, make_task, shutdown_rx, context));
+            Self {
+                prices,
+                statuses,
+                health,
+                _shutdown: shutdown,
+            }
+        }
+
+        async fn next_price(&mut self) -> Decimal {
+            tokio::time::timeout(Duration::from_secs(5), self.prices.recv())
+                .await
+                .expect("no price within 5s")
+                .unwrap()
+                .price
+        }
+
+        fn states(&self) -> Vec<ConnectionState> {
+            self.statuses.try_iter().map(|event| event.state).collect()
+        }
+
+        fn health(&self) -> PairHealth {
+            self.health.lock().unwrap()[&(Exchange::Okx, "BTC-USDT".to_string())].clone()
+        }
+    }
+
+    const NO_WAIT: Backoff = Backoff {
+        base: Duration::from_millis(1),
+        max: Duration::from_millis(1),
+        strategy: BackoffStrategy::Exponential,
+        failed_after: 5,
+        max_retries: None,
+    };
+
+    #[tokio::test]
+    async fn forwards_every_ticker_as_a_price() {
+        let exchange = MockExchange::new([Script::Stream(vec![ticker("100.5"), ticker("101")])]);
+        let mut harness = Harness::start(&exchange, NO_WAIT);
+
+        assert_eq!(harness.next_price().await, "100.5".parse().unwrap());
+        assert_eq!(harness.next_price().await, Decimal::from(101));
+        assert_eq!(harness.states()[0], ConnectionState::Connected);
+        assert_eq!(harness.health().updates, 2);
+        assert!(harness.health().send_latency.is_some());
+    }
+
+    #[test]
+    fn throughput_is_the_rate_since_the_last_summary() {
+        let (tx, _rx) = broadcast::channel(64);
+        let health = Health::default();
+        let received = Instant::now() - Duration::from_millis(3);
+        for _ in 0..30 {
+            let update = PriceUpdate::new(Exchange::Okx, "BTC-USDT", Decimal::ONE);
+            assert!(publish(&tx, &health, update, received));
+        }
+        let pair_health = |health: &Health| {
+            health.lock().unwrap()[&(Exchange::Okx, "BTC-USDT".to_string())].clone()
+        };
+        assert!(pair_health(&health).max_send_latency >= Some(Duration::from_millis(3)));
+
+        let minute = Duration::from_secs(60);
+        let counted = log_throughput(&health, Exchange::Okx, "BTC-USDT", minute, 0);
+        assert_eq!(counted, 30);
+        assert_eq!(
+            pair_health(&health).updates_per_sec,
+            Some("0.5".parse().unwrap())
+        );
+        assert_eq!(pair_health(&health).max_send_latency, None);
+
+        let counted = log_throughput(&health, Exchange::Okx, "BTC-USDT", minute, counted);
+        assert_eq!(counted, 30);
+        assert_eq!(pair_health(&health).updates_per_sec, Some(Decimal::ZERO));
+    }
+
+    #[tokio::test]
+    async fn skips_repeated_ticks() {
+        let exchange = MockExchange::new([Script::Stream(vec![
+            ticker("100"),
+            ticker("100"),
+            ticker("101"),
+        ])]);
+        let mut harness = Harness::start(&exchange, NO_WAIT);
+
+        assert_eq!(harness.next_price().await, Decimal::from(100));
+        assert_eq!(harness.next_price().await, Decimal::from(101));
+        assert!(harness.health().last_update.is_some());
+
+        // A change dropped by the throttle isn't a repeat of what was sent.
+        let exchange = MockExchange::new([Script::Paced(vec![
+            (Duration::ZERO, ticker("100")),
+            (Duration::from_millis(20), ticker("101")),
+            (Duration::from_millis(300), ticker("101")),
+        ])]);
+        let mut harness = Harness::start_throttled(&exchange, Duration::from_millis(200));
+
+        assert_eq!(harness.next_price().await, Decimal::from(100));
+        assert_eq!(harness.next_price().await, Decimal::from(101));
+    }
+
+    #[tokio::test]
+    async fn resubscribes_when_the_stream_ends() {
+        let exchange = MockExchange::new([
+            Script::Ends(vec![ticker("1"), Err(unavailable())]),
+            Script::Stream(vec![ticker("2")]),
+        ]);
+        let mut harness = Harness::start(&exchange, NO_WAIT);
+
+        assert_eq!(harness.next_price().await, Decimal::ONE);
+        assert_eq!(harness.next_price().await, Decimal::TWO);
+        assert_eq!(exchange.subscribes(), 2);
+        assert_eq!(
+            harness.states(),
+            [
+                ConnectionState::Connected,
+                ConnectionState::Reconnecting,
+                ConnectionState::Connected
+            ]
+        );
+        assert_eq!(harness.health().reconnects, 1);
+    }
+
+    #[tokio::test]
+    async fn backs_off_after_failed_subscribes() {
+        let exchange = MockExchange::new([
+            Script::Fail(unavailable()),
+            Script::Fail(unavailable()),
+            Script::Stream(vec![ticker("3")]),
+        ]);
+        let backoff = Backoff {
+            base: Duration::from_millis(20),
+            max: Duration::from_secs(1),
+            strategy: BackoffStrategy::Exponential,
+            failed_after: 2,
+            max_retries: None,
+        };
+        let started = Instant::now();
+        let mut harness = Harness::start(&exchange, backoff);
+
+        assert_eq!(harness.next_price().await, Decimal::from(3));
+        // Jitter only ever adds to the delays.
+        assert!(started.elapsed() >= backoff.delay(1) + backoff.delay(2));
+        assert_eq!(exchange.subscribes(), 3);
+        assert_eq!(
+            harness.states(),
+            [
+                ConnectionState::Reconnecting,
+                ConnectionState::Failed,
+                ConnectionState::Connected
+            ]
+        );
+        assert_eq!(harness.health().consecutive_errors, 0);
+        assert_eq!(
+            harness.health().last_error,
+            Some(TickerError::from(unavailable()))
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let exchange = MockExchange::new([
+            Script::Ends(vec![ticker("1")]),
+            Script::Fail(unavailable()),
+            Script::Fail(unavailable()),
+            Script::Stream(vec![ticker("2")]),
+        ]);
+        let backoff = Backoff {
+            max_retries: Some(2),
+            ..NO_WAIT
+        };
+        let mut harness = Harness::start(&exchange, backoff);
+
+        assert_eq!(harness.next_price().await, Decimal::ONE);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while harness.health().state != ConnectionState::Stopped {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("not given up within 5s");
+        // The stream ending and the failed subscribe are the two retries.
+        assert_eq!(exchange.subscribes(), 3);
+        assert_eq!(harness.health().reconnects, 3);
+    }
+
+    #[tokio::test]
+    async fn adds_and_removes_pairs_while_running() {
+        let config = Config::builder()
+            .add_pair("BTC-USDT")
+            .simulate(true)
+            .build()
+            .unwrap();
+        let (tx, mut prices) = broadcast::channel(16);
+        let (status_tx, _statuses) = mpsc::channel();
+        let mut monitoring = ExchangeClient::new(&config)
+            .unwrap()
+            .start_price_monitoring(tx, status_tx);
+
+        let eth = PairConfig::new("ETH-USDT");
+        assert!(monitoring.add_pair(&config, &eth).unwrap());
+        assert!(!monitoring.add_pair(&config, &eth).unwrap());
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while prices.recv().await.unwrap().pair != "ETH-USDT" {}
+        })
+        .await
+        .expect("no ETH-USDT price within 5s");
+
+        assert!(monitoring.remove_pair("okx:BTC-USDT").await.unwrap());
+        assert!(!monitoring.remove_pair("BTC-USDT").await.unwrap());
+        let health = monitoring.health().lock().unwrap().clone();
+        assert_eq!(
+            health.into_keys().collect::<Vec<_>>(),
+            [(Exchange::Okx, "ETH-USDT".to_string())]
+        );
+        monitoring.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn streams_connection_events_until_shut_down() {
+        let config = Config::builder()
+            .add_pair("BTC-USDT")
+            .simulate(true)
+            .build()
+            .unwrap();
+        let (tx, _prices) = broadcast::channel(16);
+        let (status_tx, _statuses) = mpsc::channel();
+        let mut monitoring = ExchangeClient::new(&config)
+            .unwrap()
+            .start_price_monitoring(tx, status_tx);
+        let mut events = Box::pin(monitoring.connection_events());
+
+        assert!(monitoring
+            .add_pair(&config, &PairConfig::new("ETH-USDT"))
+            .unwrap());
+        // BTC-USDT may or may not have connected before the subscription.
+        let event = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = events.next().await.unwrap();
+                if event.pair == "ETH-USDT" {
+                    return event;
+                }
+            }
+        })
+        .await
+        .expect("no ETH-USDT event within 5s");
+        assert_eq!(event.state, ConnectionState::Connected);
+
+        monitoring.shutdown().await;
+        tokio::time::timeout(Duration::from_secs(5), events.count())
+            .await
+            .expect("the stream didn't end after shutdown");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn streams_from_a_relay_on_a_unix_socket() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let path = std::env::temp_dir().join(format!("okk-relay-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let relay = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            let mut subscribe = String::new();
+            socket.read_line(&mut subscribe).await.unwrap();
+            let lines = [
+                r#"{"event":"subscribe","arg":{"channel":"tickers","instId":"BTC-USDT"}}"#,
+                r#"{"arg":{"channel":"tickers","instId":"ETH-USDT"},"data":[{"last":"3000","ts":"1700000000000"}]}"#,
+                r#"{"arg":{"channel":"tickers","instId":"BTC-USDT"},"data":[{"last":"65000.1","lastSz":"0.5","bidPx":"65000","bidSz":"","askPx":"65000.2","askSz":"1","ts":"1700000000000"}]}"#,
+            ];
+            for line in lines {
+                socket
+                    .write_all(format!("{line}\n").as_bytes())
+                    .await
+                    .unwrap();
+            }
+            subscribe
+        });
+        let client =
+            RelayClient::new(RelayUrl::parse(&format!("unix://{}", path.display())).unwrap());
+        let mut harness = Harness::run(NO_WAIT, move |pair, context| {
+            let (client, pair, context) = (client.clone(), pair.clone(), context.clone());
+            async move { stream_pair(client, &pair, context).await }.boxed()
+        });
+
+        let update = tokio::time::timeout(Duration::from_secs(5), harness.prices.recv())
+            .await
+            .expect("no price within 5s")
+            .unwrap();
+        assert_eq!(update.price, "65000.1".parse().unwrap());
+        assert_eq!(update.bid, Some(Decimal::from(65000)));
+        assert_eq!(update.exchange_timestamp_ms, Some(1_700_000_000_000));
+        let subscribe: serde_json::Value = serde_json::from_str(&relay.await.unwrap()).unwrap();
+        assert_eq!(subscribe["args"][0]["instId"], "BTC-USDT");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn background_refresh_stops_with_its_receiver() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut rx = refresh_in_background(Duration::from_millis(5), {
+            let runs = runs.clone();
+            move || {
+                let run = runs.fetch_add(1, AtomicOrdering::SeqCst);
+                async move { Some(run) }
+            }
+        });
+        rx.changed().await.unwrap();
+        assert_eq!(*rx.borrow(), Some(0));
+
+        drop(rx);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let stopped_at = runs.load(AtomicOrdering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(runs.load(AtomicOrdering::SeqCst), stopped_at);
+    }
+
+    #[test]
+    fn pairs_failing_together_share_one_backoff() {
+        let backoff = Backoff {
+            base: Duration::from_millis(10),
+            max: Duration::from_secs(1),
+            strategy: BackoffStrategy::Exponential,
+            failed_after: 5,
+            max_retries: None,
+        };
+        let gate = ReconnectGate::default();
+
+        let (since, failures) = gate.failed(&backoff, None);
+        assert_eq!(failures, 1);
+        // Another pair failing during the wait joins it.
+        assert_eq!(gate.failed(&backoff, None), (since, 1));
+        // One that already waited in it starts the next.
+        let (next, failures) = gate.failed(&backoff, Some(since));
+        assert_eq!(failures, 2);
+        assert!(next >= since);
+        // So does any once the wait is over.
+        std::thread::sleep(backoff.delay(2));
+        assert_eq!(gate.failed(&backoff, None).1, 3);
+
+        gate.connected();
+        assert_eq!(gate.failed(&backoff, None).1, 1);
+    }
+
+    #[tokio::test]
+    async fn restarts_a_task_that_panicked() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut harness = Harness::run(NO_WAIT, {
+            let runs = runs.clone();
+            move |pair, context| {
+                let first = runs.fetch_add(1, AtomicOrdering::SeqCst) == 0;
+                let update = PriceUpdate::new(pair.exchange, &pair.pair, Decimal::from(7));
+                let tx = context.tx.clone();
+                async move {
+                    if first {
+                        panic!("boom");
+                    }
+                    let _ = tx.send(update);
+                }
+                .boxed()
+            }
+        });
+
+        assert_eq!(harness.next_price().await, Decimal::from(7));
+        assert_eq!(runs.load(AtomicOrdering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_a_task_that_keeps_panicking() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let harness = Harness::run(NO_WAIT, {
+            let runs = runs.clone();
+            move |_, _| {
+                runs.fetch_add(1, AtomicOrdering::SeqCst);
+                async { panic!("boom") }.boxed()
+            }
+        });
+
+        let event = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Ok(event) = harness.statuses.try_recv() {
+                    return event;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("not given up on within 5s");
+        assert_eq!(event.state, ConnectionState::Stopped);
+        assert_eq!(runs.load(AtomicOrdering::SeqCst), MAX_RESTARTS as usize + 1);
+    }
+}
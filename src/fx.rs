@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use rust_decimal::Decimal;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::config::Config;
+use crate::exchange;
+
+/// Used without `fx_url`: the ECB reference rates, which have no USDT, so the
+/// dollar stands in for the stablecoins it is pegged to.
+pub const DEFAULT_FX_URL: &str = "https://api.frankfurter.app/latest?from=USD&to={currency}";
+/// Quote currencies converted to the display currency.
+const USD_QUOTES: [&str; 3] = ["USDT", "USDC", "USD"];
+const REFRESH: Duration = Duration::from_secs(60 * 60);
+/// Wait before trying again after a failed fetch.
+const RETRY: Duration = Duration::from_secs(60);
+
+/// The dollar to `display_currency` exchange rate, refreshed hourly in the
+/// background. The refresh stops when this is dropped.
+pub struct FxRate {
+    currency: String,
+    url: String,
+    rate: watch::Receiver<Option<Decimal>>,
+    task: JoinHandle<()>,
+}
+
+impl FxRate {
+    /// Start fetching the rate for the config's `display_currency`, if set.
+    pub fn spawn(config: &Config) -> Option<Self> {
+        let currency = config.display_currency.as_ref()?.to_uppercase();
+        let url = config
+            .fx_url
+            .clone()
+            .unwrap_or_else(|| DEFAULT_FX_URL.to_string());
+        let http = exchange::http_client(config)
+            .map_err(|err| tracing::warn!("not converting prices to {currency}: {err}"))
+            .ok()?;
+        let (tx, rate) = watch::channel(None);
+        let task = tokio::spawn({
+            let (currency, url) = (currency.clone(), url.replace("{currency}", &currency));
+            async move {
+                loop {
+                    let wait = match fetch(&http, &url, &currency).await {
+                        Ok(rate) => {
+                            tracing::info!("USD/{currency} rate: {rate}");
+                            if tx.send(Some(rate)).is_err() {
+                                break;
+                            }
+                            REFRESH
+                        }
+                        Err(err) => {
+                            tracing::warn!("failed to fetch the USD/{currency} rate: {err}");
+                            RETRY
+                        }
+                    };
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        });
+        Some(Self {
+            currency,
+            url,
+            rate,
+            task,
+        })
+    }
+
+    /// Whether this already follows `config`, so it needn't be restarted.
+    pub fn matches(&self, config: &Config) -> bool {
+        config
+            .display_currency
+            .as_ref()
+            .is_some_and(|currency| currency.eq_ignore_ascii_case(&self.currency))
+            && config.fx_url.as_deref().unwrap_or(DEFAULT_FX_URL) == self.url
+    }
+
+    /// `price` of `pair` in the display currency, or `None` if the pair isn't
+    /// quoted in dollars or the rate isn't known yet. The quote is the second
+    /// part, also of swaps and futures such as `BTC-USDT-SWAP`.
+    pub fn convert(&self, pair: &str, price: Decimal) -> Option<Decimal> {
+        let quote = pair.split('-').nth(1)?;
+        if !USD_QUOTES.contains(&quote) {
+            return None;
+        }
+        Some(price * (*self.rate.borrow())?)
+    }
+
+    /// The currency's sign where there is a common one, else its code.
+    pub fn symbol(&self) -> String {
+        match self.currency.as_str() {
+            "EUR" => "€".to_string(),
+            "GBP" => "£".to_string(),
+            "JPY" | "CNY" => "¥".to_string(),
+            "USD" => "$".to_string(),
+            code => format!("{code} "),
+        }
+    }
+}
+
+impl Drop for FxRate {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The rate at `url`, which must answer like Frankfurter:
+/// `{"rates": {"EUR": 0.92}}`.
+async fn fetch(http: &reqwest::Client, url: &str, currency: &str) -> anyhow::Result<Decimal> {
+    let body: serde_json::Value = http.get(url).send().await?.json().await?;
+    let rate = &body["rates"][currency];
+    let rate = match rate.as_str() {
+        Some(rate) => rate.parse()?,
+        None => rate
+            .as_f64()
+            .and_then(|rate| Decimal::try_from(rate).ok())
+            .ok_or_else(|| anyhow::anyhow!("no {currency} rate in the response"))?,
+    };
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Converts to `currency` at `rate`, without fetching anything.
+    fn fixed(currency: &str, rate: Option<&str>) -> FxRate {
+        let (_, rate) = watch::channel(rate.map(|rate| rate.parse().unwrap()));
+        FxRate {
+            currency: currency.to_string(),
+            url: DEFAULT_FX_URL.to_string(),
+            rate,
+            task: tokio::spawn(async {}),
+        }
+    }
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn converts_swaps_and_futures_by_their_quote() {
+        let fx = fixed("EUR", Some("0.9"));
+        for pair in ["BTC-USDT-SWAP", "BTC-USD-250328"] {
+            assert_eq!(fx.convert(pair, dec("100")), Some(dec("90")), "{pair}");
+        }
+    }
+
+    #[tokio::test]
+    async fn converts_only_dollar_quotes() {
+        let fx = fixed("EUR", Some("0.9"));
+        for pair in ["BTC-USDT", "ETH-USDC", "SOL-USD"] {
+            assert_eq!(fx.convert(pair, dec("10")), Some(dec("9")), "{pair}");
+        }
+        for pair in ["ETH-BTC", "BTC-EUR", "BTCUSDT"] {
+            assert_eq!(fx.convert(pair, dec("10")), None, "{pair}");
+        }
+    }
+
+    #[tokio::test]
+    async fn converts_nothing_until_the_rate_is_known() {
+        let fx = fixed("EUR", None);
+        assert_eq!(fx.convert("BTC-USDT", dec("100")), None);
+    }
+
+    #[tokio::test]
+    async fn matches_the_configured_currency_and_source() {
+        let fx = fixed("EUR", Some("0.9"));
+        let mut config = Config::default();
+        assert!(!fx.matches(&config));
+        config.display_currency = Some("eur".to_string());
+        assert!(fx.matches(&config));
+        config.fx_url = Some("https://fx.example.com/{currency}".to_string());
+        assert!(!fx.matches(&config));
+        config.fx_url = None;
+        config.display_currency = Some("GBP".to_string());
+        assert!(!fx.matches(&config));
+        assert_eq!(fx.symbol(), "€");
+        assert_eq!(fixed("CHF", None).symbol(), "CHF ");
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, VecDeque};
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::exchange::{Exchange, PriceUpdate};
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The most recent updates of every pair, up to `capacity` each. Once a
+/// pair's buffer is full the oldest update is evicted. With an average
+/// window, each pair's moving average is kept up to date as prices come in.
+pub struct PriceHistory {
+    capacity: usize,
+    pairs: HashMap<(Exchange, String), VecDeque<PriceUpdate>>,
+    average_window: Option<usize>,
+    averages: HashMap<(Exchange, String), RunningAverage>,
+}
+
+/// The simple moving average of a pair's last `window` prices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovingAverage {
+    pub window: usize,
+    pub value: Decimal,
+}
+
+/// The prices in a moving average window and their sum, which is adjusted as
+/// prices enter and leave rather than added up again.
+#[derive(Default)]
+struct RunningAverage {
+    prices: VecDeque<Decimal>,
+    sum: Decimal,
+}
+
+impl RunningAverage {
+    fn push(&mut self, price: Decimal, window: usize) {
+        self.prices.push_back(price);
+        self.sum += price;
+        self.shrink(window);
+    }
+
+    /// Drop the oldest prices beyond `window`.
+    fn shrink(&mut self, window: usize) {
+        while self.prices.len() > window {
+            if let Some(oldest) = self.prices.pop_front() {
+                self.sum -= oldest;
+            }
+        }
+    }
+}
+
+impl PriceHistory {
+    pub fn new(capacity: usize, average_window: Option<usize>) -> Self {
+        Self {
+            capacity,
+            pairs: HashMap::new(),
+            average_window,
+            averages: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, update: PriceUpdate) {
+        let key = (update.exchange, update.pair.clone());
+        if let Some(window) = self.average_window {
+            self.averages
+                .entry(key.clone())
+                .or_default()
+                .push(update.price, window);
+        }
+        let buffer = self.pairs.entry(key).or_default();
+        if buffer.len() >= self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(update);
+    }
+
+    /// Change the moving average window. A smaller one keeps the newest
+    /// prices; a larger one fills up with the coming ones.
+    pub fn set_average_window(&mut self, window: Option<usize>) {
+        self.average_window = window;
+        match window {
+            Some(window) => {
+                for average in self.averages.values_mut() {
+                    average.shrink(window);
+                }
+            }
+            None => self.averages.clear(),
+        }
+    }
+
+    /// The pair's moving average, once it has a full window of prices.
+    pub fn moving_average(&self, exchange: Exchange, pair: &str) -> Option<MovingAverage> {
+        let window = self.average_window?;
+        let average = self.averages.get(&(exchange, pair.to_string()))?;
+        (average.prices.len() == window).then(|| MovingAverage {
+            window,
+            value: average.sum / Decimal::from(window),
+        })
+    }
+
+    /// Change the capacity, dropping the oldest updates of pairs above it.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        for buffer in self.pairs.values_mut() {
+            let excess = buffer.len().saturating_sub(capacity);
+            buffer.drain(..excess);
+        }
+    }
+
+    /// Forget the pairs not in `pairs`.
+    pub fn retain(&mut self, pairs: &[(Exchange, String)]) {
+        self.pairs.retain(|key, _| pairs.contains(key));
+        self.averages.retain(|key, _| pairs.contains(key));
+    }
+
+    /// The pair's recent prices as a sparkline, oldest first.
+    pub fn sparkline(&self, exchange: Exchange, pair: &str) -> Option<String> {
+        let buffer = self.pairs.get(&(exchange, pair.to_string()))?;
+        Some(sparkline(buffer.iter().map(|update| update.price)))
+    }
+}
+
+/// Render prices as bars scaled between their minimum and maximum. A flat
+/// series is drawn at mid height.
+pub fn sparkline(prices: impl Iterator<Item = Decimal> + Clone) -> String {
+    let min = prices.clone().min().unwrap_or_default();
+    let max = prices.clone().max().unwrap_or_default();
+    let top = Decimal::from(BARS.len() - 1);
+    prices
+        .map(|price| {
+            let level = if max == min {
+                BARS.len() / 2 - 1
+            } else {
+                ((price - min) / (max - min) * top)
+                    .round()
+                    .to_usize()
+                    .unwrap_or(0)
+            };
+            BARS[level.min(BARS.len() - 1)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(history: &mut PriceHistory, pair: &str, prices: &[i64]) {
+        for &price in prices {
+            history.push(PriceUpdate::new(Exchange::Okx, pair, Decimal::from(price)));
+        }
+    }
+
+    fn average(history: &PriceHistory, pair: &str) -> Option<Decimal> {
+        Some(history.moving_average(Exchange::Okx, pair)?.value)
+    }
+
+    #[test]
+    fn moving_average_waits_for_a_full_window() {
+        let mut history = PriceHistory::new(10, Some(3));
+        push(&mut history, "BTC-USDT", &[1, 2]);
+        assert_eq!(average(&history, "BTC-USDT"), None);
+        push(&mut history, "BTC-USDT", &[6]);
+        assert_eq!(
+            history.moving_average(Exchange::Okx, "BTC-USDT"),
+            Some(MovingAverage {
+                window: 3,
+                value: Decimal::from(3),
+            })
+        );
+    }
+
+    #[test]
+    fn moving_average_evicts_the_oldest_price() {
+        let mut history = PriceHistory::new(2, Some(3));
+        push(&mut history, "BTC-USDT", &[1, 2, 3, 10, 20]);
+        // The average window is independent of the shorter capacity.
+        assert_eq!(average(&history, "BTC-USDT"), Some(Decimal::from(11)));
+        let running = &history.averages[&(Exchange::Okx, "BTC-USDT".to_string())];
+        assert_eq!(running.sum, Decimal::from(33));
+    }
+
+    #[test]
+    fn shrinking_the_window_keeps_the_newest_prices() {
+        let mut history = PriceHistory::new(10, Some(4));
+        push(&mut history, "BTC-USDT", &[1, 2, 3, 4]);
+        history.set_average_window(Some(2));
+
+        let running = &history.averages[&(Exchange::Okx, "BTC-USDT".to_string())];
+        assert_eq!(running.prices, [Decimal::from(3), Decimal::from(4)]);
+        assert_eq!(running.sum, Decimal::from(7));
+        assert_eq!(average(&history, "BTC-USDT"), Some("3.5".parse().unwrap()));
+
+        // Growing it again needs new prices before there is an average.
+        history.set_average_window(Some(3));
+        assert_eq!(average(&history, "BTC-USDT"), None);
+        push(&mut history, "BTC-USDT", &[8]);
+        assert_eq!(average(&history, "BTC-USDT"), Some(Decimal::from(5)));
+
+        history.set_average_window(None);
+        assert!(history.averages.is_empty());
+    }
+
+    #[test]
+    fn retain_drops_the_averages_of_removed_pairs() {
+        let mut history = PriceHistory::new(10, Some(1));
+        push(&mut history, "BTC-USDT", &[1]);
+        push(&mut history, "ETH-USDT", &[2]);
+        history.retain(&[(Exchange::Okx, "ETH-USDT".to_string())]);
+
+        assert_eq!(average(&history, "BTC-USDT"), None);
+        assert_eq!(average(&history, "ETH-USDT"), Some(Decimal::from(2)));
+        assert_eq!(history.averages.len(), 1);
+    }
+}
@@ -0,0 +1,88 @@
+#[cfg(not(feature = "tray"))]
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+#[cfg(not(feature = "tray"))]
+use std::path::PathBuf;
+
+#[cfg(not(feature = "tray"))]
+use crate::exchange::Exchange;
+use crate::exchange::PriceUpdate;
+#[cfg(not(feature = "tray"))]
+use crate::sink::PriceSink;
+
+/// The last price of each pair as saved by [`save`], or none if there is no
+/// file yet or it can't be read.
+#[cfg(feature = "tray")]
+pub fn load(path: &Path) -> Vec<PriceUpdate> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            tracing::warn!("failed to read {}: {err}", path.display());
+            return Vec::new();
+        }
+    };
+    serde_json::from_str(&content).unwrap_or_else(|err| {
+        tracing::warn!("ignoring {}: {err}", path.display());
+        Vec::new()
+    })
+}
+
+/// Write `updates` to `path` as a JSON array, for [`load`] on the next start.
+pub fn save<'a>(path: &Path, updates: impl IntoIterator<Item = &'a PriceUpdate>) {
+    let updates: Vec<&PriceUpdate> = updates.into_iter().collect();
+    let result = serde_json::to_string(&updates)
+        .map_err(std::io::Error::from)
+        .and_then(|content| fs::write(path, content));
+    match result {
+        Ok(()) => tracing::debug!("saved {} prices to {}", updates.len(), path.display()),
+        Err(err) => tracing::warn!("failed to save the prices to {}: {err}", path.display()),
+    }
+}
+
+/// Record the prices last shown on quitting: log each of them, and save
+/// them to `path` if there is one.
+pub fn snapshot<'a>(path: Option<&Path>, updates: impl IntoIterator<Item = &'a PriceUpdate>) {
+    let mut updates: Vec<&PriceUpdate> = updates.into_iter().collect();
+    updates.sort_by_key(|update| (update.exchange.to_string(), update.pair.clone()));
+    for update in &updates {
+        match serde_json::to_string(update) {
+            Ok(json) => tracing::info!("last price: {json}"),
+            Err(err) => tracing::warn!("failed to serialize the last price: {err}"),
+        }
+    }
+    if let Some(path) = path {
+        save(path, updates);
+    }
+}
+
+/// Keeps the newest update of each pair and takes a [`snapshot`] of them
+/// once the price feed ends, for running without the tray.
+#[cfg(not(feature = "tray"))]
+pub struct LastPrices {
+    path: Option<PathBuf>,
+    latest: HashMap<(Exchange, String), PriceUpdate>,
+}
+
+#[cfg(not(feature = "tray"))]
+impl LastPrices {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            latest: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(not(feature = "tray"))]
+impl PriceSink for LastPrices {
+    fn handle(&mut self, update: &PriceUpdate) {
+        self.latest
+            .insert((update.exchange, update.pair.clone()), update.clone());
+    }
+
+    fn finish(&mut self) {
+        snapshot(self.path.as_deref(), self.latest.values());
+    }
+}
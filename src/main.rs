@@ -1,174 +1,252 @@
-use std::time::Duration;
 
-use exc::prelude::*;
+#[cfg(feature = "tray")]
+mod aggregate;
+mod alerts;
+#[cfg(feature = "tray")]
+mod balance;
+mod config;
+mod error;
+mod exchange;
+#[cfg(feature = "tray")]
+mod fx;
+#[cfg(feature = "tray")]
+mod history;
+mod last_prices;
+mod number_format;
+#[cfg(feature = "tray")]
+mod price_icon;
+mod price_log;
+mod relay;
+#[cfg(feature = "tray")]
+mod settings;
+mod sink;
+#[cfg(feature = "tray")]
+mod theme;
+#[cfg(feature = "tray")]
+mod ui;
+
+use anyhow::Context;
+use clap::Parser;
 use futures::StreamExt;
 use tracing_subscriber::prelude::*;
-use std::thread::sleep;
 
-use chrono::Local;
-use tao::event_loop::{ControlFlow, EventLoopBuilder};
-use tray_icon::{
-    menu::{AboutMetadata, Menu, MenuEvent, MenuItem, PredefinedMenuItem},
-    TrayIconBuilder, TrayIconEvent,
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+use config::Config;
+use exchange::{Exchange, ExchangeClient};
+use price_log::PriceLog;
+#[cfg(feature = "tray")]
+use ui::TrayUI;
+#[cfg(not(feature = "tray"))]
+use {
+    alerts::Alerts,
+    exchange::{ConnectionEvent, MonitoringHandle},
+    std::sync::mpsc::Receiver,
 };
-use tokio::task;
 
-use std::sync::mpsc::{channel, Receiver, Sender};
+/// Looked up in order in each of [`config_dirs`]; the first one that exists
+/// is used. Without any, the defaults are written to the first in the current
+/// directory.
+const CONFIG_PATHS: &[&str] = &["config.toml", "config.yaml", "config.yml", "config.json"];
+
+/// Where config files are looked for, in order: the current directory,
+/// `$XDG_CONFIG_HOME/crypto-ticker` and `~/.config/crypto-ticker`.
+fn config_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(".")];
+    if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        dirs.push(PathBuf::from(xdg).join("crypto-ticker"));
+    }
+    if let Some(home) = std::env::var_os("HOME").filter(|dir| !dir.is_empty()) {
+        dirs.push(PathBuf::from(home).join(".config").join("crypto-ticker"));
+    }
+    dirs
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM where there is one.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(err) => tracing::warn!("failed to listen for SIGTERM: {err}"),
+        }
+    }
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        tracing::warn!("failed to listen for Ctrl-C: {err}");
+        std::future::pending::<()>().await;
+    }
+}
 
+/// See crypto coin prices in the menu bar.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Config file to use instead of looking for one in the current directory
+    /// and the user's config directory.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Print the effective config (defaults, file and environment variables
+    /// merged) as TOML and exit.
+    #[arg(long)]
+    print_config: bool,
+    /// Print every price as a JSON line instead of showing the tray, until
+    /// interrupted.
+    #[arg(long)]
+    print_prices: bool,
+    /// List the spot pairs the exchanges trade, as they are written in
+    /// `trading_pairs`, optionally only those containing FILTER, and exit.
+    #[arg(long, value_name = "FILTER", num_args = 0..=1, default_missing_value = "")]
+    list_pairs: Option<String>,
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
     let fmt = tracing_subscriber::fmt::layer()
     .with_writer(std::io::stderr)
     .with_filter(tracing_subscriber::EnvFilter::new(
-        std::env::var("RUST_LOG").unwrap_or_else(|_| "exc_okx=debug,okx_streams=debug".into()),
+        std::env::var("RUST_LOG").unwrap_or_else(|_| "okk=info,exc_okx=info".into()),
     ));
 tracing_subscriber::registry().with(fmt).init();
 
-let exchange = Okx::endpoint()
-    .ws_ping_timeout(Duration::from_secs(5))
-    .ws_connection_timeout(Duration::from_secs(2))
-    .connect_exc();
-
-let (tx, rx) = channel();  // 创建一个channel
-
-// 克隆发送端用于任务
-let tx_clone = tx.clone();
-
-let handles = ["BTC-USDT"]
-    .into_iter()
-    .map(|inst| {
-        let mut client = exchange.clone();
-        let tx = tx_clone.clone();  // 在这里克隆tx
-        tokio::spawn(async move {
-            loop {
-                tracing::info!("{inst}");
-                match { client.subscribe_tickers(inst).await } {
-                    Ok(mut stream) => {
-                        while let Some(c) = stream.next().await {
-                            match c {
-                                Ok(c) => {
-                                    tracing::info!("{0}", c.last);
-                                    tx.send(c.last).unwrap_or_else(|_| tracing::warn!("Failed to send data to channel"));
-                                },
-                                Err(err) => {
-                                    tracing::error!("{err}");
-                                }
-                            }
-                        }
-                        tracing::warn!("stream is dead; reconnecting..");
-                    },
-                    Err(err) => {
-                        tracing::error!("request error: {err}; retrying..");
-                    }
-                }
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-            }
-        })
-    })
-    .collect::<Vec<_>>();
-
-    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/icons/icon.png");
-    // println!("{}", &path);
-    // for h in handles {
-    //     let _ = h.await;
-    // }
-
-
-
-    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/icons/icon.png");
-    println!("{}", &path);
-    let icon = load_icon(std::path::Path::new(path));
-
-    let event_loop = EventLoopBuilder::new().build();
-
-    let tray_menu = Menu::new();
-
-    let quit_i = MenuItem::new("Quit", true, None);
-    tray_menu.append_items(&[
-
-
-        &PredefinedMenuItem::separator(),
-        &quit_i,
-    ]);
-
-    let mut tray_icon = Some(
-        TrayIconBuilder::new()
-            .with_id("1")
-            .with_menu(Box::new(tray_menu))
-            .with_title("ss")
-            .with_tooltip("tao - awesome windowing lib")
-            .with_icon(icon)
-            .build()
-            .unwrap(),
-    );
-
-
-    // let mut icon = TrayIcon;
-
-    let menu_channel = MenuEvent::receiver();
-    let tray_channel = TrayIconEvent::receiver();
-    let current_time = Local::now().format("%H:%M:%S").to_string();
-    
-    event_loop.run(move |_event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
-        
-        
-        let current_time = Local::now().format("%H:%M:%S").to_string();
-
-        // std::thread::sleep(std::time::Duration::from_secs(3));
-     
-        // if let Some(ref mut tray) = tray_icon {
-        //     tray.set_title(Some(&current_time));
-        // }
-
-        if let Ok(price) = rx.try_recv() {
-            let price_str = format!("BTC-USDT: {}", price);
-            if let Some(ref mut tray) = tray_icon {
-                tray.set_title(Some(&price_str));
+let config_path = args.config.or_else(|| {
+    config_dirs()
+        .iter()
+        .flat_map(|dir| CONFIG_PATHS.iter().map(move |name| dir.join(name)))
+        .find(|path| path.exists())
+});
+let config = match &config_path {
+    Some(path) => {
+        let mut config = Config::from_file(path)?;
+        config.merge_env()?;
+        config
+    }
+    None => {
+        if !(args.print_config || args.print_prices || args.list_pairs.is_some()) {
+            if let Err(err) = Config::default().save_to_file(CONFIG_PATHS[0]) {
+                tracing::warn!("{err}");
             }
         }
-
-        // if let Ok(price) = rx.try_recv() {
-        //     let price_str = format!("BTC-USDT: {}", price);
-        //     if let Some(ref mut tray) = tray_icon {
-        //         tray.set_title(Some(&current_time));
-        //     }
-        // }
-
-        if let Ok(event) = menu_channel.try_recv() {
-            if event.id == quit_i.id() {
-                tray_icon.take();
-             
-
-                *control_flow = ControlFlow::Exit;
+        Config::from_env()?
+    }
+};
+if args.print_config {
+    print!("{}", config.to_toml()?);
+    return Ok(());
+}
+if let Some(filter) = &args.list_pairs {
+    let http = exchange::http_client(&config)?;
+    let filter = filter.to_uppercase();
+    for exchange in Exchange::ALL {
+        match exchange.fetch_pairs(&http).await {
+            Ok(mut pairs) => {
+                pairs.retain(|pair| pair.contains(&filter));
+                pairs.sort();
+                let prefix = exchange.to_string().to_lowercase();
+                for pair in pairs {
+                    println!("{prefix}:{pair}");
+                }
             }
-            
-            println!("{event:?}");
+            Err(err) => tracing::warn!("failed to list the pairs on {exchange}: {err}"),
         }
+    }
+    return Ok(());
+}
+if args.print_prices {
+    let mut prices = std::pin::pin!(ExchangeClient::new(&config)?.price_stream());
+    while let Some(update) = prices.next().await {
+        println!("{}", serde_json::to_string(&update)?);
+    }
+    return Ok(());
+}
+#[cfg(feature = "tray")]
+let config_path = config_path.unwrap_or_else(|| PathBuf::from(CONFIG_PATHS[0]));
+
+let (tx, _) = broadcast::channel(config.max_buffer_size);  // 创建一个channel
+// Waited for on the way out, so that they can write what they still hold.
+let mut sinks = Vec::new();
+#[cfg(feature = "tray")]
+let rx = {
+    let (tray_sink, rx) = sink::TraySink::new(config.max_buffer_size);
+    sinks.push(sink::spawn(tray_sink, tx.subscribe()));
+    rx
+};
+#[cfg(not(feature = "tray"))]
+{
+    sinks.push(sink::spawn(sink::LogSink, tx.subscribe()));
+    sinks.push(sink::spawn(Alerts::new(&config.alerts), tx.subscribe()));
+    let path = config.last_prices_path.as_ref().map(PathBuf::from);
+    sinks.push(sink::spawn(last_prices::LastPrices::new(path), tx.subscribe()));
+}
+if let Some(path) = &config.price_log_path {
+    let price_log = PriceLog::open(path)
+        .with_context(|| format!("failed to open price log {path}"))?;
+    sinks.push(sink::spawn(price_log, tx.subscribe()));
+}
+if let Some(secs) = config.metrics_interval_secs {
+    sinks.push(sink::spawn(sink::MetricsSink::new(Duration::from_secs(secs)), tx.subscribe()));
+}
+let (status_tx, status_rx) = channel();
 
-        if let Ok(event) = tray_channel.try_recv() {
-            println!("{event:?}");
-            // tray_channel.set_title("a");
-        }
-    });
+let client = ExchangeClient::new(&config)?;
+let monitoring = client.start_price_monitoring(tx, status_tx);
 
-    
+#[cfg(not(feature = "tray"))]
+{
+    run_headless(status_rx, monitoring, sinks).await;
     Ok(())
-
- 
 }
 
+#[cfg(feature = "tray")]
+{
+let reloads = if config.watch_config {
+    Some(Config::watch(&config_path)?)
+} else {
+    None
+};
 
+// The tray loop owns the main thread, so the signal is passed to it to quit.
+let (quit_tx, quit_rx) = channel();
+tokio::spawn(async move {
+    shutdown_signal().await;
+    tracing::info!("interrupted; quitting");
+    let _ = quit_tx.send(());
+});
+
+    let tick_decimals = client.tick_decimals().await;
+    TrayUI::new(&config, config_path, tick_decimals)
+        .run(rx, status_rx, monitoring, sinks, reloads, quit_rx)
+}
+}
 
-fn load_icon(path: &std::path::Path) -> tray_icon::Icon {
-    let (icon_rgba, icon_width, icon_height) = {
-        let image = image::open(path)
-            .expect("Failed to open icon path")
-            .into_rgba8();
-        let (width, height) = image.dimensions();
-        let rgba = image.into_raw();
-        (rgba, width, height)
-    };
-    tray_icon::Icon::from_rgba(icon_rgba, icon_width, icon_height).expect("Failed to open icon")
-}
\ No newline at end of file
+/// Without the tray: log every connection change until interrupted. The
+/// prices and alerts are handled by the `sinks`, which are waited for once
+/// the feed has stopped, so that the snapshot and the log are complete.
+#[cfg(not(feature = "tray"))]
+async fn run_headless(
+    status_rx: Receiver<ConnectionEvent>,
+    monitoring: MonitoringHandle,
+    sinks: Vec<tokio::task::JoinHandle<()>>,
+) {
+    std::thread::spawn(move || {
+        for event in status_rx {
+            tracing::info!("{} {}: {}", event.exchange, event.pair, event.state);
+        }
+    });
+    shutdown_signal().await;
+    tracing::info!("quitting");
+    monitoring.shutdown().await;
+    futures::future::join_all(sinks).await;
+}
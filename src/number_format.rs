@@ -0,0 +1,301 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+#[cfg(feature = "tray")]
+use rust_decimal::RoundingStrategy;
+use serde::{Deserialize, Serialize};
+
+/// Significant figures a price keeps however few decimals are configured, so
+/// that e.g. SHIB at 0.00001234 doesn't show as 0.00.
+const MIN_SIGNIFICANT: u32 = 4;
+/// The largest scale a `Decimal` has.
+const MAX_DECIMALS: u32 = 28;
+
+/// At least `decimals`, and more if that would leave `amount` with fewer than
+/// [`MIN_SIGNIFICANT`] significant figures.
+pub fn decimals_for(amount: Decimal, decimals: u32) -> u32 {
+    let mut amount = amount.abs();
+    if amount.is_zero() {
+        return decimals;
+    }
+    let needed = if amount < Decimal::ONE {
+        // Zeros between the decimal mark and the first significant digit.
+        let mut zeros = 0;
+        while amount * Decimal::TEN < Decimal::ONE {
+            amount *= Decimal::TEN;
+            zeros += 1;
+        }
+        zeros + MIN_SIGNIFICANT
+    } else {
+        let mut digits = 1;
+        while amount >= Decimal::TEN {
+            amount /= Decimal::TEN;
+            digits += 1;
+        }
+        MIN_SIGNIFICANT.saturating_sub(digits)
+    };
+    decimals.max(needed).min(MAX_DECIMALS)
+}
+
+/// How prices are cut to the decimal places shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    /// To the nearest, halves away from zero like exchanges do.
+    #[default]
+    Nearest,
+    /// Down, so the price shown is never more than the real one.
+    Floor,
+    /// Up.
+    Ceil,
+}
+
+impl RoundingMode {
+    const ALL: [RoundingMode; 3] = [Self::Nearest, Self::Floor, Self::Ceil];
+
+    #[cfg(feature = "tray")]
+    pub fn round(self, amount: Decimal, decimals: u32) -> Decimal {
+        let strategy = match self {
+            Self::Nearest => RoundingStrategy::MidpointAwayFromZero,
+            Self::Floor => RoundingStrategy::ToNegativeInfinity,
+            Self::Ceil => RoundingStrategy::ToPositiveInfinity,
+        };
+        amount.round_dp_with_strategy(decimals, strategy)
+    }
+}
+
+impl fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nearest => write!(f, "nearest"),
+            Self::Floor => write!(f, "floor"),
+            Self::Ceil => write!(f, "ceil"),
+        }
+    }
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!("unsupported rounding mode {s}, expected one of nearest, floor, ceil")
+            })
+    }
+}
+
+/// Suffixes for [`abbreviate`], largest first.
+#[cfg(feature = "tray")]
+const ABBREVIATIONS: [(i64, char); 3] = [(1_000_000_000, 'B'), (1_000_000, 'M'), (1_000, 'k')];
+
+/// `amount` shortened to three significant figures with a k, M or B suffix,
+/// such as `65.4k`, or `None` if it is under a thousand.
+#[cfg(feature = "tray")]
+pub fn abbreviate(amount: Decimal) -> Option<(Decimal, char)> {
+    let index = ABBREVIATIONS
+        .iter()
+        .position(|(unit, _)| amount.abs() >= Decimal::from(*unit))?;
+    let scaled = scale(amount, ABBREVIATIONS[index]);
+    // 999,950 rounds to 1000k, which is 1M.
+    match index.checked_sub(1) {
+        Some(larger) if scaled.0.abs() >= Decimal::ONE_THOUSAND => {
+            Some(scale(amount, ABBREVIATIONS[larger]))
+        }
+        _ => Some(scaled),
+    }
+}
+
+/// `amount` in `unit`s, rounded to three significant figures.
+#[cfg(feature = "tray")]
+fn scale(amount: Decimal, (unit, suffix): (i64, char)) -> (Decimal, char) {
+    let scaled = amount / Decimal::from(unit);
+    let decimals = match scaled.abs() {
+        scaled if scaled >= Decimal::ONE_HUNDRED => 0,
+        scaled if scaled >= Decimal::TEN => 1,
+        _ => 2,
+    };
+    (scaled.round_dp(decimals).normalize(), suffix)
+}
+
+/// Digit grouping and decimal mark used when showing prices.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumberLocale {
+    /// `1,234,567.89`
+    #[default]
+    #[serde(rename = "en-US")]
+    EnUs,
+    /// `1.234.567,89`
+    #[serde(rename = "de-DE")]
+    DeDe,
+    /// `1 234 567,89`, grouped with a narrow no-break space.
+    #[serde(rename = "fr-FR")]
+    FrFr,
+    /// `1'234'567.89`
+    #[serde(rename = "de-CH")]
+    DeCh,
+}
+
+impl NumberLocale {
+    const ALL: [NumberLocale; 4] = [Self::EnUs, Self::DeDe, Self::FrFr, Self::DeCh];
+
+    /// The group separator and decimal mark.
+    #[cfg(feature = "tray")]
+    fn separators(self) -> (char, char) {
+        match self {
+            Self::EnUs => (',', '.'),
+            Self::DeDe => ('.', ','),
+            Self::FrFr => ('\u{202f}', ','),
+            Self::DeCh => ('\'', '.'),
+        }
+    }
+
+    /// Regroup a number formatted the Rust way, such as `-1234567.89`.
+    #[cfg(feature = "tray")]
+    pub fn format(self, number: &str) -> String {
+        let (group, mark) = self.separators();
+        let (sign, digits) = match number.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", number),
+        };
+        let (int, frac) = match digits.split_once('.') {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (digits, None),
+        };
+        let mut formatted = sign.to_string();
+        for (i, digit) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i) % 3 == 0 {
+                formatted.push(group);
+            }
+            formatted.push(digit);
+        }
+        if let Some(frac) = frac {
+            formatted.push(mark);
+            formatted.push_str(frac);
+        }
+        formatted
+    }
+}
+
+impl fmt::Display for NumberLocale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EnUs => write!(f, "en-US"),
+            Self::DeDe => write!(f, "de-DE"),
+            Self::FrFr => write!(f, "fr-FR"),
+            Self::DeCh => write!(f, "de-CH"),
+        }
+    }
+}
+
+impl FromStr for NumberLocale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|locale| locale.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                format!("unsupported locale {s}, expected one of en-US, de-DE, fr-FR, de-CH")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn abbreviates_to_three_significant_figures() {
+        let cases = [
+            ("1234", "1.23", 'k'),
+            ("65432", "65.4", 'k'),
+            ("-65432", "-65.4", 'k'),
+            ("999499", "999", 'k'),
+            ("1500000", "1.5", 'M'),
+            ("2000000000", "2", 'B'),
+            ("1234000000000", "1234", 'B'),
+        ];
+        for (amount, scaled, suffix) in cases {
+            assert_eq!(
+                abbreviate(dec(amount)),
+                Some((dec(scaled), suffix)),
+                "{amount}"
+            );
+        }
+        assert_eq!(abbreviate(dec("999.99")), None);
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn abbreviation_moves_up_a_unit_when_rounding_reaches_a_thousand() {
+        assert_eq!(abbreviate(dec("999950")), Some((dec("1"), 'M')));
+        assert_eq!(abbreviate(dec("-999950")), Some((dec("-1"), 'M')));
+        assert_eq!(abbreviate(dec("999950000")), Some((dec("1"), 'B')));
+        assert_eq!(abbreviate(dec("999500")), Some((dec("1"), 'M')));
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn groups_digits_for_every_locale() {
+        let cases = [
+            (NumberLocale::EnUs, "1,234,567.89"),
+            (NumberLocale::DeDe, "1.234.567,89"),
+            (NumberLocale::FrFr, "1\u{202f}234\u{202f}567,89"),
+            (NumberLocale::DeCh, "1'234'567.89"),
+        ];
+        for (locale, formatted) in cases {
+            assert_eq!(locale.format("1234567.89"), formatted, "{locale}");
+        }
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn formats_signs_integers_and_short_numbers() {
+        let locale = NumberLocale::DeDe;
+        assert_eq!(locale.format("-1234567.89"), "-1.234.567,89");
+        assert_eq!(locale.format("-123456"), "-123.456");
+        assert_eq!(locale.format("1000"), "1.000");
+        assert_eq!(locale.format("999.5"), "999,5");
+        assert_eq!(locale.format("-12"), "-12");
+        assert_eq!(locale.format("0.00001234"), "0,00001234");
+    }
+
+    #[test]
+    fn decimals_keep_four_significant_figures() {
+        assert_eq!(decimals_for(dec("0.00001234"), 2), 8);
+        assert_eq!(decimals_for(dec("-0.00001234"), 2), 8);
+        assert_eq!(decimals_for(dec("0.5"), 2), 4);
+        assert_eq!(decimals_for(dec("1.5"), 2), 3);
+        assert_eq!(decimals_for(dec("65000"), 2), 2);
+        assert_eq!(decimals_for(dec("65000"), 0), 0);
+        assert_eq!(decimals_for(Decimal::ZERO, 2), 2);
+        assert_eq!(decimals_for(Decimal::ZERO, 0), 0);
+    }
+
+    #[cfg(feature = "tray")]
+    #[test]
+    fn rounds_by_mode() {
+        let cases = [
+            (RoundingMode::Nearest, "1.005", "1.01"),
+            (RoundingMode::Nearest, "-1.005", "-1.01"),
+            (RoundingMode::Nearest, "1.004", "1.00"),
+            (RoundingMode::Floor, "1.019", "1.01"),
+            (RoundingMode::Floor, "-1.011", "-1.02"),
+            (RoundingMode::Ceil, "1.011", "1.02"),
+            (RoundingMode::Ceil, "-1.019", "-1.01"),
+            (RoundingMode::Floor, "-1.01", "-1.01"),
+        ];
+        for (mode, amount, rounded) in cases {
+            assert_eq!(mode.round(dec(amount), 2), dec(rounded), "{mode} {amount}");
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+/// Size of one font pixel in the icon, in image pixels.
+const SCALE: u32 = 4;
+/// Glyph height in font pixels.
+const GLYPH_HEIGHT: u32 = 5;
+/// Empty font pixels around the text and between glyphs.
+const MARGIN: u32 = 1;
+/// Rendered icons kept before the cache is cleared.
+const MAX_CACHED: usize = 64;
+
+/// A glyph's width in font pixels and its rows, leftmost pixel in the highest
+/// of the `width` low bits.
+struct Glyph {
+    width: u32,
+    rows: [u8; GLYPH_HEIGHT as usize],
+}
+
+/// A small built-in pixel font covering what a formatted price can contain.
+/// Other characters are drawn as a space.
+fn glyph(c: char) -> Glyph {
+    let (width, rows) = match c {
+        '0' => (3, [0b111, 0b101, 0b101, 0b101, 0b111]),
+        '1' => (3, [0b010, 0b110, 0b010, 0b010, 0b111]),
+        '2' => (3, [0b111, 0b001, 0b111, 0b100, 0b111]),
+        '3' => (3, [0b111, 0b001, 0b111, 0b001, 0b111]),
+        '4' => (3, [0b101, 0b101, 0b111, 0b001, 0b001]),
+        '5' => (3, [0b111, 0b100, 0b111, 0b001, 0b111]),
+        '6' => (3, [0b111, 0b100, 0b111, 0b101, 0b111]),
+        '7' => (3, [0b111, 0b001, 0b001, 0b001, 0b001]),
+        '8' => (3, [0b111, 0b101, 0b111, 0b101, 0b111]),
+        '9' => (3, [0b111, 0b101, 0b111, 0b001, 0b111]),
+        '.' => (1, [0b0, 0b0, 0b0, 0b0, 0b1]),
+        ',' => (1, [0b0, 0b0, 0b0, 0b1, 0b1]),
+        '\'' => (1, [0b1, 0b1, 0b0, 0b0, 0b0]),
+        '-' => (3, [0b000, 0b000, 0b111, 0b000, 0b000]),
+        _ => (2, [0; 5]),
+    };
+    Glyph { width, rows }
+}
+
+/// Draw `text` in `color` on a transparent background. The image is as wide
+/// as the text needs.
+pub fn render(text: &str, color: [u8; 3]) -> (Vec<u8>, u32, u32) {
+    let glyphs: Vec<Glyph> = text.chars().map(glyph).collect();
+    let text_width: u32 = glyphs.iter().map(|g| g.width).sum::<u32>()
+        + MARGIN * (glyphs.len() as u32).saturating_sub(1);
+    let width = (text_width + 2 * MARGIN) * SCALE;
+    let height = (GLYPH_HEIGHT + 2 * MARGIN) * SCALE;
+    let mut rgba = vec![0u8; (width * height * 4) as usize];
+
+    let mut x0 = MARGIN;
+    for glyph in &glyphs {
+        for (row, bits) in glyph.rows.iter().enumerate() {
+            for col in 0..glyph.width {
+                if bits >> (glyph.width - 1 - col) & 1 == 0 {
+                    continue;
+                }
+                let (fx, fy) = (x0 + col, MARGIN + row as u32);
+                for y in fy * SCALE..(fy + 1) * SCALE {
+                    for x in fx * SCALE..(fx + 1) * SCALE {
+                        let i = ((y * width + x) * 4) as usize;
+                        rgba[i..i + 4].copy_from_slice(&[color[0], color[1], color[2], 0xff]);
+                    }
+                }
+            }
+        }
+        x0 += glyph.width + MARGIN;
+    }
+    (rgba, width, height)
+}
+
+/// Renders prices into tray icons, reusing the icon when the same price is
+/// shown again.
+#[derive(Default)]
+pub struct PriceIcons {
+    cache: HashMap<(String, [u8; 3]), tray_icon::Icon>,
+}
+
+impl PriceIcons {
+    pub fn get(&mut self, text: &str, color: [u8; 3]) -> tray_icon::Icon {
+        let key = (text.to_string(), color);
+        if let Some(icon) = self.cache.get(&key) {
+            return icon.clone();
+        }
+        if self.cache.len() >= MAX_CACHED {
+            self.cache.clear();
+        }
+        let (rgba, width, height) = render(text, color);
+        let icon = tray_icon::Icon::from_rgba(rgba, width, height)
+            .expect("rendered price icon has a valid size");
+        self.cache.insert(key, icon.clone());
+        icon
+    }
+}
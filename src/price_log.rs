@@ -0,0 +1,74 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::exchange::PriceUpdate;
+use crate::sink::PriceSink;
+
+/// How often buffered lines are written out, and the log file checked for
+/// having been moved away.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends every price update to a file as one JSON object per line.
+pub struct PriceLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    flushed_at: Instant,
+}
+
+impl PriceLog {
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let writer = BufWriter::new(append(&path)?);
+        Ok(Self {
+            path,
+            writer,
+            flushed_at: Instant::now(),
+        })
+    }
+
+    fn write(&mut self, update: &PriceUpdate) {
+        let result = serde_json::to_writer(&mut self.writer, update)
+            .map_err(io::Error::from)
+            .and_then(|()| self.writer.write_all(b"\n"));
+        if let Err(err) = result {
+            tracing::warn!("failed to write to {}: {err}", self.path.display());
+        }
+    }
+
+    /// Write out buffered lines. If the file was moved or deleted, e.g. by
+    /// log rotation, a new one is started at `path`.
+    fn flush(&mut self) {
+        self.flushed_at = Instant::now();
+        if let Err(err) = self.writer.flush() {
+            tracing::warn!("failed to write to {}: {err}", self.path.display());
+        }
+        if !self.path.exists() {
+            match append(&self.path) {
+                Ok(file) => self.writer = BufWriter::new(file),
+                Err(err) => tracing::warn!("failed to reopen {}: {err}", self.path.display()),
+            }
+        }
+    }
+}
+
+impl PriceSink for PriceLog {
+    fn handle(&mut self, update: &PriceUpdate) {
+        self.write(update);
+    }
+
+    fn tick(&mut self) {
+        if self.flushed_at.elapsed() >= FLUSH_INTERVAL {
+            self.flush();
+        }
+    }
+
+    fn finish(&mut self) {
+        self.flush();
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
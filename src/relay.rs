@@ -0,0 +1,230 @@
+use std::fmt;
+use std::future::ready;
+use std::path::PathBuf;
+use std::task::{Context, Poll};
+
+use exc::core::transport::websocket::connector::WsConnector;
+use exc::core::types::{SubscribeTickers, Ticker, TickerStream};
+use exc::ExchangeError;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use tower::Service;
+
+/// Where the relay listens: a websocket, or a Unix socket speaking one JSON
+/// message per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayUrl {
+    Ws(String),
+    Unix(PathBuf),
+}
+
+impl RelayUrl {
+    /// Accepts `ws://`/`wss://` URLs and `unix:///path/to/socket`.
+    pub fn parse(url: &str) -> anyhow::Result<Self> {
+        if let Some(path) = url.strip_prefix("unix://") {
+            if !cfg!(unix) {
+                anyhow::bail!("unix:// sockets are not supported on this platform");
+            }
+            if path.is_empty() {
+                anyhow::bail!("expected a socket path after unix://");
+            }
+            return Ok(RelayUrl::Unix(path.into()));
+        }
+        let parsed = reqwest::Url::parse(url)?;
+        if !matches!(parsed.scheme(), "ws" | "wss") {
+            anyhow::bail!("expected a ws://, wss:// or unix:// URL");
+        }
+        Ok(RelayUrl::Ws(url.to_string()))
+    }
+}
+
+impl fmt::Display for RelayUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelayUrl::Ws(url) => f.write_str(url),
+            RelayUrl::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// Subscribes to tickers at a local relay instead of OKX. Every subscription
+/// opens its own connection, sends OKX's subscribe request on it, and reads
+/// messages of OKX's `tickers` channel, skipping those of other instruments
+/// and anything without ticker data. A relay that just broadcasts may ignore
+/// the request.
+#[derive(Debug, Clone)]
+pub struct RelayClient {
+    url: RelayUrl,
+}
+
+impl RelayClient {
+    pub fn new(url: RelayUrl) -> Self {
+        Self { url }
+    }
+
+    pub fn url(&self) -> &RelayUrl {
+        &self.url
+    }
+}
+
+impl Service<SubscribeTickers> for RelayClient {
+    type Response = TickerStream;
+    type Error = ExchangeError;
+    type Future = BoxFuture<'static, Result<TickerStream, ExchangeError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: SubscribeTickers) -> Self::Future {
+        let url = self.url.clone();
+        let inst = req.instrument.to_string();
+        async move {
+            let subscribe = serde_json::json!({
+                "op": "subscribe",
+                "args": [{ "channel": "tickers", "instId": inst }],
+            })
+            .to_string();
+            let messages = match url {
+                RelayUrl::Ws(url) => connect_ws(&url, subscribe).await?,
+                RelayUrl::Unix(path) => connect_unix(path, subscribe).await?,
+            };
+            let tickers = messages.filter_map(move |text| ready(parse_ticker(&inst, &text)));
+            Ok(tickers.boxed())
+        }
+        .boxed()
+    }
+}
+
+fn unavailable(err: impl Into<anyhow::Error>) -> ExchangeError {
+    ExchangeError::Unavailable(err.into())
+}
+
+/// The relay's text messages, until the connection fails or closes.
+async fn connect_ws(
+    url: &str,
+    subscribe: String,
+) -> Result<BoxStream<'static, String>, ExchangeError> {
+    let uri = url.parse().map_err(unavailable)?;
+    let mut ws = WsConnector::new().call(uri).await.map_err(unavailable)?;
+    ws.send(subscribe.into()).await.map_err(unavailable)?;
+    let messages = ws
+        .scan((), |_, msg| {
+            ready(match msg {
+                Ok(msg) => Some(msg),
+                Err(err) => {
+                    tracing::warn!("relay connection failed: {err}");
+                    None
+                }
+            })
+        })
+        .filter_map(|msg| ready(msg.into_text().ok()));
+    Ok(messages.boxed())
+}
+
+/// The relay's lines, until the connection fails or closes.
+#[cfg(unix)]
+async fn connect_unix(
+    path: PathBuf,
+    subscribe: String,
+) -> Result<BoxStream<'static, String>, ExchangeError> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let mut socket = tokio::net::UnixStream::connect(&path)
+        .await
+        .map_err(|err| unavailable(anyhow::anyhow!("{}: {err}", path.display())))?;
+    socket
+        .write_all(format!("{subscribe}\n").as_bytes())
+        .await
+        .map_err(unavailable)?;
+    let lines = BufReader::new(socket).lines();
+    let lines = futures::stream::unfold(lines, |mut lines| async move {
+        match lines.next_line().await {
+            Ok(Some(line)) => Some((line, lines)),
+            Ok(None) => None,
+            Err(err) => {
+                tracing::warn!("relay connection failed: {err}");
+                None
+            }
+        }
+    });
+    Ok(lines.boxed())
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(
+    _path: PathBuf,
+    _subscribe: String,
+) -> Result<BoxStream<'static, String>, ExchangeError> {
+    Err(ExchangeError::Other(anyhow::anyhow!(
+        "unix:// sockets are not supported on this platform"
+    )))
+}
+
+#[derive(Deserialize)]
+struct Message {
+    arg: MessageArg,
+    #[serde(default)]
+    data: Vec<TickerData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageArg {
+    channel: String,
+    inst_id: String,
+}
+
+/// OKX sends numbers as strings, and empty ones for missing values.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TickerData {
+    last: String,
+    #[serde(default)]
+    last_sz: String,
+    #[serde(default)]
+    bid_px: String,
+    #[serde(default)]
+    bid_sz: String,
+    #[serde(default)]
+    ask_px: String,
+    #[serde(default)]
+    ask_sz: String,
+    ts: String,
+}
+
+/// The latest ticker in a message for `inst`; `None` for other messages.
+fn parse_ticker(inst: &str, text: &str) -> Option<Result<Ticker, ExchangeError>> {
+    let unreadable = |err: &dyn std::fmt::Display| {
+        ExchangeError::UnexpectedResponseType(format!("relay message `{text}`: {err}"))
+    };
+    let message: Message = match serde_json::from_str(text) {
+        Ok(message) => message,
+        // Event replies such as subscribe acknowledgements have no `arg` with
+        // a channel; only complain about what isn't JSON at all.
+        Err(err) if err.is_data() => return None,
+        Err(err) => return Some(Err(unreadable(&err))),
+    };
+    if message.arg.channel != "tickers" || !message.arg.inst_id.eq_ignore_ascii_case(inst) {
+        return None;
+    }
+    let data = message.data.into_iter().last()?;
+    let decimal = |s: &str| s.parse::<Decimal>().ok();
+    let ticker = (|| {
+        let ms: i128 = data.ts.parse().ok()?;
+        Some(Ticker {
+            ts: time::OffsetDateTime::from_unix_timestamp_nanos(ms * 1_000_000).ok()?,
+            last: decimal(&data.last)?,
+            size: decimal(&data.last_sz).unwrap_or_default(),
+            buy: None,
+            bid: decimal(&data.bid_px),
+            bid_size: decimal(&data.bid_sz),
+            ask: decimal(&data.ask_px),
+            ask_size: decimal(&data.ask_sz),
+        })
+    })();
+    Some(ticker.ok_or_else(|| unreadable(&"no valid `last` and `ts`")))
+}
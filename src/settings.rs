@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use tray_icon::menu::{CheckMenuItem, MenuId, MenuItem, PredefinedMenuItem, Submenu};
+
+use crate::config::{Config, TitleValue};
+use crate::error::Result;
+
+/// Update intervals offered in the menu, in seconds.
+const INTERVALS: [u64; 5] = [1, 5, 10, 30, 60];
+
+/// A change picked from the settings menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingsChange {
+    Interval(u64),
+    TitleValue(TitleValue),
+    /// Stop monitoring the pair, as written in `trading_pairs`.
+    RemovePair(String),
+}
+
+/// The "Settings" submenu of the tray. Changes are written to the config
+/// file and applied like a reload of it.
+pub struct SettingsMenu {
+    submenu: Submenu,
+    intervals: Vec<(u64, CheckMenuItem)>,
+    /// The interval currently ticked.
+    interval: u64,
+    show_change: CheckMenuItem,
+    title_value: TitleValue,
+    remove: Submenu,
+    pairs: Vec<(String, MenuItem)>,
+    edit_file: MenuItem,
+}
+
+impl SettingsMenu {
+    pub fn new(config: &Config) -> Self {
+        let submenu = Submenu::new("Settings", true);
+        let interval = Submenu::new("Update interval", true);
+        let intervals: Vec<(u64, CheckMenuItem)> = INTERVALS
+            .iter()
+            .map(|&secs| {
+                (
+                    secs,
+                    CheckMenuItem::new(format!("{secs}s"), true, false, None),
+                )
+            })
+            .collect();
+        for (_, item) in &intervals {
+            let _ = interval.append(item);
+        }
+        let show_change = CheckMenuItem::new("Show change in title", true, false, None);
+        let remove = Submenu::new("Remove pair", true);
+        let edit_file = MenuItem::new("Edit config file…", true, None);
+        if let Err(err) = submenu.append_items(&[
+            &interval,
+            &show_change,
+            &remove,
+            &PredefinedMenuItem::separator(),
+            &edit_file,
+        ]) {
+            tracing::warn!("failed to build the settings menu: {err}");
+        }
+        let mut menu = Self {
+            submenu,
+            intervals,
+            interval: config.update_interval_secs,
+            show_change,
+            title_value: config.title_value,
+            remove,
+            pairs: Vec::new(),
+            edit_file,
+        };
+        menu.update(config);
+        menu
+    }
+
+    pub fn submenu(&self) -> &Submenu {
+        &self.submenu
+    }
+
+    /// Reflect `config`: tick its interval and title value, and list its
+    /// pairs.
+    pub fn update(&mut self, config: &Config) {
+        self.interval = config.update_interval_secs;
+        self.tick_interval();
+        self.title_value = config.title_value;
+        self.show_change
+            .set_checked(self.title_value == TitleValue::Change);
+        for (_, item) in self.pairs.drain(..) {
+            let _ = self.remove.remove(&item);
+        }
+        // The last pair can't go, a config needs at least one.
+        let removable = config.trading_pairs.len() > 1;
+        for pair_config in &config.trading_pairs {
+            let item = MenuItem::new(&pair_config.pair, removable, None);
+            let _ = self.remove.append(&item);
+            self.pairs.push((pair_config.pair.clone(), item));
+        }
+    }
+
+    fn tick_interval(&self) {
+        for (secs, item) in &self.intervals {
+            item.set_checked(*secs == self.interval);
+        }
+    }
+
+    /// Whether `id` is "Edit config file…", for what the menu can't do such
+    /// as adding pairs or picking an icon.
+    pub fn is_edit_file(&self, id: &MenuId) -> bool {
+        self.edit_file.id() == id
+    }
+
+    /// The change a click on the menu item `id` asks for, if it is one of ours.
+    pub fn change_for(&self, id: &MenuId) -> Option<SettingsChange> {
+        if let Some((secs, _)) = self.intervals.iter().find(|(_, item)| item.id() == id) {
+            // Clicking toggled the tick; it follows the config once applied.
+            self.tick_interval();
+            return Some(SettingsChange::Interval(*secs));
+        }
+        if self.show_change.id() == id {
+            self.show_change
+                .set_checked(self.title_value == TitleValue::Change);
+            let value = match self.title_value {
+                TitleValue::Price => TitleValue::Change,
+                TitleValue::Change => TitleValue::Price,
+            };
+            return Some(SettingsChange::TitleValue(value));
+        }
+        self.pairs
+            .iter()
+            .find(|(_, item)| item.id() == id)
+            .map(|(pair, _)| SettingsChange::RemovePair(pair.clone()))
+    }
+}
+
+/// Apply `change` to the config file at `path` and return the config to run
+/// with, the `TICKER_*` variables applied. The file is left alone if the
+/// result doesn't validate. Environment overrides are not written to it.
+pub fn save(change: &SettingsChange, path: &Path) -> Result<Config> {
+    let mut config = Config::from_file(path)?;
+    match change {
+        SettingsChange::Interval(secs) => config.update_interval_secs = *secs,
+        SettingsChange::TitleValue(value) => config.title_value = *value,
+        SettingsChange::RemovePair(pair) => config.trading_pairs.retain(|p| &p.pair != pair),
+    }
+    config.validate()?;
+    config.save_to_file(path)?;
+    config.merge_env()?;
+    Ok(config)
+}
+
+/// Open `path` with the platform's default application.
+pub fn open_in_editor(path: &Path) {
+    if let Err(err) = open::that_detached(path) {
+        tracing::warn!("failed to open {}: {err}", path.display());
+    }
+}
@@ -0,0 +1,235 @@
+use std::any::type_name;
+use std::collections::HashMap;
+#[cfg(feature = "tray")]
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::{Duration, Instant};
+
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::exchange::{Exchange, PriceUpdate};
+
+/// How often [`PriceSink::tick`] is called.
+const TICK: Duration = Duration::from_millis(100);
+
+/// A consumer of the price feed: the tray, the price log, the logs.
+pub trait PriceSink: Send + 'static {
+    /// Take the next update. Sinks see every update in order, unless they
+    /// fall more than the feed's capacity behind.
+    fn handle(&mut self, update: &PriceUpdate);
+
+    /// Called regularly, for sinks that buffer or report periodically.
+    fn tick(&mut self) {}
+
+    /// Whether the sink has no use for more updates, which stops it.
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    /// Called once the price feed ends or the sink closed.
+    fn finish(&mut self) {}
+}
+
+/// Feed `sink` the updates from `rx` on a task of its own, until the feed
+/// ends or the sink closes. The task ends once the sink has finished.
+pub fn spawn(mut sink: impl PriceSink, mut rx: broadcast::Receiver<PriceUpdate>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(TICK);
+        while !sink.is_closed() {
+            tokio::select! {
+                update = rx.recv() => match update {
+                    Ok(update) => sink.handle(&update),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("{} is behind; skipped {skipped} updates", sink_name(&sink))
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = tick.tick() => sink.tick(),
+            }
+        }
+        sink.finish();
+    })
+}
+
+/// The sink's type without its path, e.g. `PriceLog`.
+fn sink_name<S>(_: &S) -> &'static str {
+    let name = type_name::<S>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Hands the updates to a synchronous consumer such as the tray loop, over
+/// a bounded channel it can poll. Updates that don't fit are held back, only
+/// the newest per pair, and offered again on the next tick. Closes once the
+/// consumer is gone.
+#[cfg(feature = "tray")]
+pub struct TraySink {
+    tx: SyncSender<PriceUpdate>,
+    pending: Vec<PriceUpdate>,
+    closed: bool,
+}
+
+#[cfg(feature = "tray")]
+impl TraySink {
+    pub fn new(capacity: usize) -> (Self, Receiver<PriceUpdate>) {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let sink = Self {
+            tx,
+            pending: Vec::new(),
+            closed: false,
+        };
+        (sink, rx)
+    }
+
+    /// Send as many of the held back updates as fit, oldest first.
+    fn flush(&mut self) {
+        while !self.pending.is_empty() {
+            match self.tx.try_send(self.pending.remove(0)) {
+                Ok(()) => {}
+                Err(TrySendError::Full(update)) => {
+                    self.pending.insert(0, update);
+                    break;
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tray")]
+impl PriceSink for TraySink {
+    fn handle(&mut self, update: &PriceUpdate) {
+        self.pending
+            .retain(|held| held.exchange != update.exchange || held.pair != update.pair);
+        self.pending.push(update.clone());
+        self.flush();
+    }
+
+    fn tick(&mut self) {
+        self.flush();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Logs every update, for running without the tray.
+#[cfg(not(feature = "tray"))]
+pub struct LogSink;
+
+#[cfg(not(feature = "tray"))]
+impl PriceSink for LogSink {
+    fn handle(&mut self, update: &PriceUpdate) {
+        tracing::info!("{} {}: {}", update.exchange, update.pair, update.price);
+    }
+}
+
+/// Logs, every `period`, how many updates each pair got and how long they
+/// took from the exchange on average.
+pub struct MetricsSink {
+    period: Duration,
+    since: Instant,
+    pairs: HashMap<(Exchange, String), PairMetrics>,
+}
+
+#[derive(Default)]
+struct PairMetrics {
+    updates: u64,
+    /// Sum and count of the latencies of the updates that had one.
+    latency_ms: i64,
+    timed: i64,
+}
+
+impl MetricsSink {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            since: Instant::now(),
+            pairs: HashMap::new(),
+        }
+    }
+
+    fn report(&mut self) {
+        let mut lines: Vec<String> = self
+            .pairs
+            .drain()
+            .map(|((exchange, pair), metrics)| {
+                let mut line = format!("{exchange} {pair}: {} updates", metrics.updates);
+                if metrics.timed > 0 {
+                    let average = metrics.latency_ms / metrics.timed;
+                    line.push_str(&format!(", {average} ms behind the exchange on average"));
+                }
+                line
+            })
+            .collect();
+        lines.sort();
+        for line in lines {
+            tracing::info!("in the last {}s: {line}", self.period.as_secs());
+        }
+    }
+}
+
+impl PriceSink for MetricsSink {
+    fn handle(&mut self, update: &PriceUpdate) {
+        let metrics = self
+            .pairs
+            .entry((update.exchange, update.pair.clone()))
+            .or_default();
+        metrics.updates += 1;
+        if let Some(latency) = update.latency_ms() {
+            metrics.latency_ms += latency;
+            metrics.timed += 1;
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.since.elapsed() >= self.period {
+            self.since = Instant::now();
+            self.report();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tray"))]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn update(exchange: Exchange, pair: &str, price: u32) -> PriceUpdate {
+        PriceUpdate::new(exchange, pair, price.into())
+    }
+
+    #[test]
+    fn tray_sink_holds_back_the_newest_update_per_pair() {
+        let (mut sink, rx) = TraySink::new(1);
+        sink.handle(&update(Exchange::Okx, "BTC-USDT", 1));
+        sink.handle(&update(Exchange::Okx, "BTC-USDT", 2));
+        sink.handle(&update(Exchange::Okx, "ETH-USDT", 3));
+        sink.handle(&update(Exchange::Binance, "BTC-USDT", 4));
+        sink.handle(&update(Exchange::Okx, "BTC-USDT", 5));
+
+        let mut received = Vec::new();
+        while let Ok(update) = rx.try_recv() {
+            received.push(update.price);
+            sink.tick();
+        }
+        let prices = [1, 3, 4, 5].map(Decimal::from);
+        assert_eq!(received, prices);
+        assert!(!sink.is_closed());
+    }
+
+    #[test]
+    fn tray_sink_closes_once_the_consumer_is_gone() {
+        let (mut sink, rx) = TraySink::new(1);
+        sink.handle(&update(Exchange::Okx, "BTC-USDT", 1));
+        sink.handle(&update(Exchange::Okx, "ETH-USDT", 2));
+        assert!(!sink.is_closed());
+        drop(rx);
+        sink.tick();
+        assert!(sink.is_closed());
+    }
+}
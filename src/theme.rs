@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::config::IconTheme;
+
+/// How often `auto` checks whether the system appearance changed.
+const POLL: Duration = Duration::from_secs(5);
+
+/// The kind of menu bar the icon is drawn for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Appearance {
+    Light,
+    Dark,
+}
+
+/// The appearance the icon follows: a fixed one, or the system's, looked up
+/// in the background for `auto`. The lookup stops when this is dropped.
+pub struct Theme {
+    fixed: Option<Appearance>,
+    system: Option<(watch::Receiver<Option<Appearance>>, JoinHandle<()>)>,
+}
+
+impl Theme {
+    pub fn new(theme: IconTheme) -> Self {
+        let fixed = match theme {
+            IconTheme::Light => Some(Appearance::Light),
+            IconTheme::Dark => Some(Appearance::Dark),
+            IconTheme::Auto => None,
+        };
+        let system = fixed.is_none().then(|| {
+            let (tx, rx) = watch::channel(None);
+            let task = tokio::spawn(async move {
+                loop {
+                    if let Some(appearance) = detect().await {
+                        tx.send_if_modified(|shown| {
+                            let changed = *shown != Some(appearance);
+                            if changed {
+                                tracing::debug!("system appearance: {appearance:?}");
+                                *shown = Some(appearance);
+                            }
+                            changed
+                        });
+                    }
+                    if tx.is_closed() {
+                        break;
+                    }
+                    tokio::time::sleep(POLL).await;
+                }
+            });
+            (rx, task)
+        });
+        Self { fixed, system }
+    }
+
+    /// The appearance to draw for; dark until the system's is known.
+    pub fn appearance(&self) -> Appearance {
+        self.fixed
+            .or_else(|| self.system.as_ref().and_then(|(rx, _)| *rx.borrow()))
+            .unwrap_or(Appearance::Dark)
+    }
+
+    /// Whether the system appearance changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        let Some((rx, _)) = self.system.as_mut() else {
+            return false;
+        };
+        let changed = rx.has_changed().unwrap_or(false);
+        rx.borrow_and_update();
+        changed
+    }
+}
+
+impl Drop for Theme {
+    fn drop(&mut self) {
+        if let Some((_, task)) = &self.system {
+            task.abort();
+        }
+    }
+}
+
+/// The system appearance, from the same setting the desktop's own theme
+/// switch changes, or `None` if it can't be read.
+#[cfg(target_os = "macos")]
+async fn detect() -> Option<Appearance> {
+    // The key only exists in dark mode.
+    let output = Command::new("defaults")
+        .args(["read", "-g", "AppleInterfaceStyle"])
+        .output()
+        .await
+        .ok()?;
+    let dark = output.status.success() && String::from_utf8_lossy(&output.stdout).contains("Dark");
+    Some(if dark {
+        Appearance::Dark
+    } else {
+        Appearance::Light
+    })
+}
+
+/// The system appearance, from the same setting the desktop's own theme
+/// switch changes, or `None` if it can't be read.
+#[cfg(target_os = "windows")]
+async fn detect() -> Option<Appearance> {
+    let output = Command::new("reg")
+        .args([
+            "query",
+            r"HKCU\Software\Microsoft\Windows\CurrentVersion\Themes\Personalize",
+            "/v",
+            "SystemUsesLightTheme",
+        ])
+        .output()
+        .await
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.split_whitespace().last()? {
+        "0x0" => Some(Appearance::Dark),
+        "0x1" => Some(Appearance::Light),
+        _ => None,
+    }
+}
+
+/// The system appearance, from the same setting the desktop's own theme
+/// switch changes, or `None` if it can't be read. GNOME and most desktops
+/// following it set `color-scheme`; older ones only name a dark GTK theme.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+async fn detect() -> Option<Appearance> {
+    let scheme = gsettings("color-scheme").await;
+    if scheme
+        .as_deref()
+        .is_some_and(|scheme| scheme.contains("prefer-dark"))
+    {
+        return Some(Appearance::Dark);
+    }
+    let gtk_theme = gsettings("gtk-theme").await;
+    if scheme.is_none() && gtk_theme.is_none() {
+        return None;
+    }
+    let dark = gtk_theme.is_some_and(|name| name.to_ascii_lowercase().contains("dark"));
+    Some(if dark {
+        Appearance::Dark
+    } else {
+        Appearance::Light
+    })
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+async fn gsettings(key: &str) -> Option<String> {
+    let output = Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", key])
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,1671 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use chrono::{Local, TimeZone};
+use rust_decimal::Decimal;
+
+use tao::event::Event;
+use tao::event_loop::{ControlFlow, EventLoop, EventLoopBuilder};
+use tray_icon::{
+    menu::{AboutMetadata, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu},
+    ClickType, TrayIcon, TrayIconBuilder, TrayIconEvent,
+};
+
+use tokio::task::JoinHandle;
+
+use crate::aggregate::{AggregatedPrice, Aggregator};
+use crate::alerts::{self, Alerts};
+use crate::balance::Balance;
+use crate::config::{ChangeBaseline, ClickAction, Config, IconTheme, TitleStyle, TitleValue};
+use crate::exchange::{
+    self, ConnectionEvent, ConnectionState, Exchange, Health, MonitoringHandle, PriceUpdate,
+};
+use crate::fx::FxRate;
+use crate::history::PriceHistory;
+use crate::last_prices;
+use crate::number_format::{self, NumberLocale, RoundingMode};
+use crate::price_icon::PriceIcons;
+use crate::settings::{self, SettingsMenu};
+use crate::theme::{Appearance, Theme};
+
+/// Tooltip until there is a price or a problem to show.
+const DEFAULT_TOOLTIP: &str = "Crypto ticker";
+/// Shown when `icon_path` can't be loaded.
+const DEFAULT_ICON: &[u8] = include_bytes!("../icons/icon.png");
+const UP_TINT: [u8; 3] = [0x1e, 0xb9, 0x54];
+const DOWN_TINT: [u8; 3] = [0xe5, 0x39, 0x35];
+const FLASH_TINT: [u8; 3] = [0xff, 0xc1, 0x07];
+/// How long the icon stays highlighted after a move of `flash_threshold_pct`.
+const FLASH: Duration = Duration::from_secs(1);
+/// How long `icon_path_alert` is shown after an alert fired.
+const ALERT_ICON: Duration = Duration::from_secs(30);
+/// Shown in the title until the first price arrives.
+const CONNECTING: &str = "Connecting…";
+/// Shown in the title while the price feed is paused.
+const PAUSED: &str = "(paused)";
+/// Shown in the title after a price restored from `last_prices_path`.
+const LAST_KNOWN: &str = "(last known)";
+/// Price text colours drawn into the icon on a light menu bar, darker than
+/// the tints so they stand out against it.
+const LIGHT_TEXT: [u8; 3] = [0x21, 0x21, 0x21];
+const LIGHT_UP_TEXT: [u8; 3] = [0x1b, 0x7f, 0x3b];
+const LIGHT_DOWN_TEXT: [u8; 3] = [0xc6, 0x28, 0x28];
+/// Price text colours on a dark menu bar.
+const DARK_TEXT: [u8; 3] = [0xee, 0xee, 0xee];
+const DARK_UP_TEXT: [u8; 3] = [0x4c, 0xd9, 0x64];
+const DARK_DOWN_TEXT: [u8; 3] = [0xff, 0x6b, 0x68];
+/// Price updates taken off the queue per loop iteration at most.
+const MAX_UPDATES_PER_TICK: usize = 256;
+/// Bounds of how often the tray loop wakes to take new prices when nothing
+/// else wakes it, see [`TrayUI::tick`].
+const MIN_TICK: Duration = Duration::from_millis(50);
+const MAX_TICK: Duration = Duration::from_secs(1);
+/// How often the loop without a tray checks whether to quit.
+const QUIT_POLL: Duration = Duration::from_millis(250);
+/// Most tray icons shown with `icon_per_pair`, the main one included.
+const MAX_PAIR_ICONS: usize = 8;
+/// Decimal places of a pair without `decimals` whose tick size couldn't be
+/// looked up.
+const DEFAULT_DECIMALS: u32 = 2;
+
+/// Direction of a pair's price relative to its previous update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Up,
+    Down,
+    Neutral,
+}
+
+impl Direction {
+    /// The first update for a pair has no previous price and is neutral.
+    fn between(previous: Option<Decimal>, current: Decimal) -> Self {
+        match previous.map(|previous| current.cmp(&previous)) {
+            Some(Ordering::Greater) => Direction::Up,
+            Some(Ordering::Less) => Direction::Down,
+            _ => Direction::Neutral,
+        }
+    }
+
+    fn arrow(self) -> &'static str {
+        match self {
+            Direction::Up => "▲ ",
+            Direction::Down => "▼ ",
+            Direction::Neutral => "",
+        }
+    }
+
+    fn text_color(self, appearance: Appearance) -> [u8; 3] {
+        match (appearance, self) {
+            (Appearance::Light, Direction::Up) => LIGHT_UP_TEXT,
+            (Appearance::Light, Direction::Down) => LIGHT_DOWN_TEXT,
+            (Appearance::Light, Direction::Neutral) => LIGHT_TEXT,
+            (Appearance::Dark, Direction::Up) => DARK_UP_TEXT,
+            (Appearance::Dark, Direction::Down) => DARK_DOWN_TEXT,
+            (Appearance::Dark, Direction::Neutral) => DARK_TEXT,
+        }
+    }
+}
+
+/// Which of the [`DirectionIcons`] a tray icon shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IconKind {
+    Direction(Direction),
+    /// Nothing is connected.
+    Disconnected,
+    /// An alert fired in the last [`ALERT_ICON`].
+    Alert,
+}
+
+/// The tray icon plus green and red tinted variants for price moves, and an
+/// amber one to flash on large moves. The configured icons for being
+/// disconnected and for alerts are `None` if unset or unreadable.
+struct DirectionIcons {
+    neutral: tray_icon::Icon,
+    up: tray_icon::Icon,
+    down: tray_icon::Icon,
+    flash: tray_icon::Icon,
+    disconnected: Option<tray_icon::Icon>,
+    alert: Option<tray_icon::Icon>,
+}
+
+impl DirectionIcons {
+    fn load(path: &Path, disconnected: Option<&Path>, alert: Option<&Path>) -> Self {
+        let (rgba, width, height) = load_rgba(path);
+        let icon =
+            |rgba| tray_icon::Icon::from_rgba(rgba, width, height).expect("Failed to open icon");
+        Self {
+            up: icon(tint(&rgba, UP_TINT)),
+            down: icon(tint(&rgba, DOWN_TINT)),
+            flash: icon(tint(&rgba, FLASH_TINT)),
+            neutral: icon(rgba),
+            disconnected: disconnected.and_then(load_icon_variant),
+            alert: alert.and_then(load_icon_variant),
+        }
+    }
+
+    /// The main icon stands in for an unset variant.
+    fn get(&self, kind: IconKind) -> tray_icon::Icon {
+        match kind {
+            IconKind::Direction(Direction::Up) => self.up.clone(),
+            IconKind::Direction(Direction::Down) => self.down.clone(),
+            IconKind::Direction(Direction::Neutral) => self.neutral.clone(),
+            IconKind::Disconnected => self.disconnected.as_ref().unwrap_or(&self.neutral).clone(),
+            IconKind::Alert => self.alert.as_ref().unwrap_or(&self.neutral).clone(),
+        }
+    }
+}
+
+/// A pair's own tray icon with `icon_per_pair`, beside the main one.
+struct PairIcon {
+    tray: TrayIcon,
+    /// `None` while the icon shows a rendered price.
+    shown_icon: Option<IconKind>,
+}
+
+/// One disabled menu entry per monitored pair, labelled with its latest price.
+struct PairMenu {
+    menu: Menu,
+    items: HashMap<(Exchange, String), MenuItem>,
+    /// "Open on exchange", with an entry per pair.
+    open: Submenu,
+    links: Vec<((Exchange, String), MenuItem)>,
+}
+
+impl PairMenu {
+    fn new(menu: Menu) -> Self {
+        Self {
+            menu,
+            items: HashMap::new(),
+            open: Submenu::new("Open on exchange", true),
+            links: Vec::new(),
+        }
+    }
+
+    /// Show an entry for each of `pairs`, in order, at the top of the menu and
+    /// under "Open on exchange". Entries of pairs that stay keep their label;
+    /// new ones start without data.
+    fn set_pairs(
+        &mut self,
+        pairs: &[(Exchange, String)],
+        label: impl Fn(&Exchange, &str) -> String,
+    ) {
+        for item in self.items.values() {
+            let _ = self.menu.remove(item);
+        }
+        let mut items = std::mem::take(&mut self.items);
+        for (position, key) in pairs.iter().enumerate() {
+            let item = items
+                .remove(key)
+                .unwrap_or_else(|| MenuItem::new(label(&key.0, &key.1), false, None));
+            if let Err(err) = self.menu.insert(&item, position) {
+                tracing::warn!("failed to add {} {} to the menu: {err}", key.0, key.1);
+            }
+            self.items.insert(key.clone(), item);
+        }
+
+        for (_, item) in self.links.drain(..) {
+            let _ = self.open.remove(&item);
+        }
+        for key in pairs {
+            let item = MenuItem::new(format!("{} {}", key.0, key.1), true, None);
+            let _ = self.open.append(&item);
+            self.links.push((key.clone(), item));
+        }
+    }
+
+    /// The pair whose "Open on exchange" entry has `id`.
+    fn link(&self, id: &MenuId) -> Option<&(Exchange, String)> {
+        self.links
+            .iter()
+            .find(|(_, item)| item.id() == id)
+            .map(|(key, _)| key)
+    }
+
+    fn set_label(&self, exchange: Exchange, pair: &str, label: String) {
+        if let Some(item) = self.items.get(&(exchange, pair.to_string())) {
+            item.set_text(label);
+        }
+    }
+}
+
+pub struct TrayUI {
+    icon_path: PathBuf,
+    icon_path_disconnected: Option<PathBuf>,
+    icon_path_alert: Option<PathBuf>,
+    /// Where settings changed from the menu are saved.
+    config_path: PathBuf,
+    settings: SettingsMenu,
+    /// Monitored pairs in config order, without duplicates, see
+    /// [`configured_pairs`].
+    pairs: Vec<(Exchange, String)>,
+    /// The pair always shown in the title, first in `pairs`.
+    primary_pair: Option<(Exchange, String)>,
+    show_exchange: bool,
+    /// Decimal places per pair, resolved from the pair and global settings
+    /// and the tick size.
+    decimals: HashMap<(Exchange, String), u32>,
+    /// Decimal places of each pair's tick size, looked up at startup.
+    tick_decimals: HashMap<(Exchange, String), u32>,
+    /// Smallest move in percent worth showing, per pair.
+    min_change: HashMap<(Exchange, String), Decimal>,
+    /// Show a price that didn't move enough anyway after this long.
+    max_unchanged: Duration,
+    /// Move in percent between two updates that flashes the icon.
+    flash_threshold: Option<Decimal>,
+    title_style: TitleStyle,
+    title_value: TitleValue,
+    change_baseline: ChangeBaseline,
+    /// Configured `reference_price` per pair.
+    reference_prices: HashMap<(Exchange, String), Decimal>,
+    /// The first price of each pair since the start, whatever the baseline,
+    /// so that switching to `session` shows the change since then.
+    session_open: HashMap<(Exchange, String), Decimal>,
+    number_locale: NumberLocale,
+    rounding_mode: RoundingMode,
+    alerts: Alerts,
+    /// Without any price for this long the tray shows "Disconnected", even if
+    /// no pair reported a connection problem. Catches streams that hang silently.
+    stale_after: Duration,
+    stale_marker: String,
+    /// When the newest update of each pair was received, even one not worth
+    /// showing, in milliseconds since the epoch. Reset on resume; repeated
+    /// prices only show in the pairs' health.
+    heard: HashMap<(Exchange, String), i64>,
+    /// Pairs whose newest update is older than `stale_after`, while the
+    /// others are still updating.
+    stale: HashSet<(Exchange, String)>,
+    /// Cycle the title through the pairs at this period instead of showing
+    /// the most recently updated one.
+    title_rotation: Option<Duration>,
+    /// Shortest time between two redraws for new prices.
+    min_redraw: Duration,
+    left_click: ClickAction,
+    double_click: ClickAction,
+    /// Set when the price is drawn into the icon instead of the title.
+    price_icons: Option<PriceIcons>,
+    icon_theme: IconTheme,
+    /// The appearance the rendered price is coloured for.
+    theme: Theme,
+    icon_per_pair: bool,
+    /// Recent prices of each pair, for the tooltip sparkline.
+    history: PriceHistory,
+    /// Set when pairs on several exchanges are shown as one price.
+    aggregator: Option<Aggregator>,
+    /// Set when prices are shown in `display_currency`.
+    fx: Option<FxRate>,
+    /// Set when the account balance is shown.
+    balance: Option<Balance>,
+    /// Where the shown prices are saved on quitting, besides being logged.
+    last_prices_path: Option<PathBuf>,
+    /// Pairs still showing the price saved by the last run.
+    restored: HashSet<(Exchange, String)>,
+}
+
+impl TrayUI {
+    /// `tick_decimals` caps the precision of pairs without their own
+    /// `decimals`, see [`ExchangeClient::tick_decimals`].
+    ///
+    /// [`ExchangeClient::tick_decimals`]: crate::exchange::ExchangeClient::tick_decimals
+    pub fn new(
+        config: &Config,
+        config_path: impl Into<PathBuf>,
+        tick_decimals: HashMap<(Exchange, String), u32>,
+    ) -> Self {
+        let config_path = config_path.into();
+        Self {
+            icon_path: config.get_icon_path(&config_path),
+            icon_path_disconnected: config.get_icon_path_disconnected(&config_path),
+            icon_path_alert: config.get_icon_path_alert(&config_path),
+            config_path,
+            settings: SettingsMenu::new(config),
+            pairs: configured_pairs(config),
+            primary_pair: primary_pair(config),
+            show_exchange: config.show_exchange,
+            decimals: pair_decimals(config, &tick_decimals),
+            tick_decimals,
+            min_change: pair_min_change(config),
+            max_unchanged: Duration::from_secs(config.max_unchanged_secs),
+            flash_threshold: config.flash_threshold_pct,
+            title_style: config.title_style,
+            title_value: config.title_value,
+            change_baseline: config.change_baseline,
+            reference_prices: pair_reference_prices(config),
+            session_open: HashMap::new(),
+            number_locale: config.number_locale,
+            rounding_mode: config.rounding_mode,
+            alerts: Alerts::new(&config.alerts),
+            stale_after: config.stale_after(),
+            stale_marker: config.stale_marker.clone(),
+            heard: HashMap::new(),
+            stale: HashSet::new(),
+            title_rotation: config.title_rotation_secs.map(Duration::from_secs),
+            min_redraw: Duration::from_millis(config.min_redraw_ms),
+            left_click: config.left_click,
+            double_click: config.double_click,
+            price_icons: config.render_price_in_icon.then(PriceIcons::default),
+            icon_theme: config.icon_theme,
+            theme: Theme::new(config.icon_theme),
+            icon_per_pair: config.icon_per_pair,
+            history: PriceHistory::new(config.max_buffer_size, config.moving_average_window),
+            aggregator: config.aggregate_exchanges.then(Aggregator::default),
+            fx: FxRate::spawn(config),
+            balance: Balance::spawn(config),
+            last_prices_path: config.last_prices_path.as_ref().map(PathBuf::from),
+            restored: HashSet::new(),
+        }
+    }
+
+    /// Apply the display settings of a reloaded config. The icon is only
+    /// loaded at startup.
+    fn reconfigure(&mut self, config: &Config) {
+        self.settings.update(config);
+        self.pairs = configured_pairs(config);
+        self.primary_pair = primary_pair(config);
+        self.show_exchange = config.show_exchange;
+        self.decimals = pair_decimals(config, &self.tick_decimals);
+        self.min_change = pair_min_change(config);
+        self.max_unchanged = Duration::from_secs(config.max_unchanged_secs);
+        self.flash_threshold = config.flash_threshold_pct;
+        self.title_style = config.title_style;
+        self.title_value = config.title_value;
+        self.change_baseline = config.change_baseline;
+        self.reference_prices = pair_reference_prices(config);
+        self.number_locale = config.number_locale;
+        self.rounding_mode = config.rounding_mode;
+        self.alerts = Alerts::new(&config.alerts);
+        self.stale_after = config.stale_after();
+        self.stale_marker = config.stale_marker.clone();
+        self.heard.retain(|key, _| self.pairs.contains(key));
+        self.stale.retain(|key| self.pairs.contains(key));
+        self.title_rotation = config.title_rotation_secs.map(Duration::from_secs);
+        self.min_redraw = Duration::from_millis(config.min_redraw_ms);
+        self.left_click = config.left_click;
+        self.double_click = config.double_click;
+        self.history.set_capacity(config.max_buffer_size);
+        self.history
+            .set_average_window(config.moving_average_window);
+        self.history.retain(&self.pairs);
+        self.session_open.retain(|key, _| self.pairs.contains(key));
+        self.restored.retain(|key| self.pairs.contains(key));
+        self.last_prices_path = config.last_prices_path.as_ref().map(PathBuf::from);
+        if config.render_price_in_icon != self.price_icons.is_some() {
+            self.price_icons = config.render_price_in_icon.then(PriceIcons::default);
+        }
+        if config.icon_theme != self.icon_theme {
+            self.icon_theme = config.icon_theme;
+            self.theme = Theme::new(config.icon_theme);
+        }
+        self.icon_per_pair = config.icon_per_pair;
+        match self.aggregator.as_mut() {
+            Some(aggregator) if config.aggregate_exchanges => aggregator.retain(&self.pairs),
+            _ => self.aggregator = config.aggregate_exchanges.then(Aggregator::default),
+        }
+        let fx_changed = match &self.fx {
+            Some(fx) => !fx.matches(config),
+            None => config.display_currency.is_some(),
+        };
+        if fx_changed {
+            self.fx = FxRate::spawn(config);
+        }
+        if config.show_balance != self.balance.is_some() {
+            self.balance = Balance::spawn(config);
+        }
+    }
+
+    /// Open the config file for editing. Without a watcher the edits would
+    /// only apply on restart, so one is started if needed.
+    fn edit_config(&self, reloads: &mut Option<Receiver<Config>>) {
+        settings::open_in_editor(&self.config_path);
+        if reloads.is_none() {
+            *reloads = Config::watch(&self.config_path)
+                .map_err(|err| tracing::warn!("{err}"))
+                .ok();
+        }
+    }
+
+    /// Whether `update` moved too little from `shown` to replace it, which
+    /// stops being the case once `shown` is `max_unchanged` old.
+    fn is_negligible(&self, shown: &PriceUpdate, update: &PriceUpdate) -> bool {
+        let Some(min_change) = self.min_change.get(&(update.exchange, update.pair.clone())) else {
+            return false;
+        };
+        let age_ms = update.timestamp_ms.saturating_sub(shown.timestamp_ms);
+        if shown.price.is_zero() || age_ms >= self.max_unchanged.as_millis() as i64 {
+            return false;
+        }
+        let moved_pct = ((update.price - shown.price) / shown.price * Decimal::ONE_HUNDRED).abs();
+        moved_pct < *min_change
+    }
+
+    /// Whether the move from `previous` to `price` is large enough to flash.
+    fn is_flash_move(&self, previous: Decimal, price: Decimal) -> bool {
+        let Some(threshold) = self.flash_threshold else {
+            return false;
+        };
+        !previous.is_zero()
+            && ((price - previous) / previous * Decimal::ONE_HUNDRED).abs() >= threshold
+    }
+
+    /// The prices saved by the last run for the monitored pairs, which are
+    /// shown as [`LAST_KNOWN`] until their first new price.
+    fn restore_last_prices(&mut self) -> Vec<PriceUpdate> {
+        let Some(path) = &self.last_prices_path else {
+            return Vec::new();
+        };
+        let updates: Vec<PriceUpdate> = last_prices::load(path)
+            .into_iter()
+            .filter(|update| {
+                self.pairs
+                    .iter()
+                    .any(|(exchange, pair)| *exchange == update.exchange && *pair == update.pair)
+            })
+            .collect();
+        self.restored = updates
+            .iter()
+            .map(|update| (update.exchange, update.pair.clone()))
+            .collect();
+        updates
+    }
+
+    fn snapshot_last_prices<'a>(&self, updates: impl IntoIterator<Item = &'a PriceUpdate>) {
+        last_prices::snapshot(self.last_prices_path.as_deref(), updates);
+    }
+
+    /// "About", with the version and what is being monitored.
+    fn about_item(&self) -> PredefinedMenuItem {
+        let mut exchanges: Vec<Exchange> = Vec::new();
+        for (exchange, _) in &self.pairs {
+            if !exchanges.contains(exchange) {
+                exchanges.push(*exchange);
+            }
+        }
+        let exchanges: Vec<String> = exchanges.iter().map(ToString::to_string).collect();
+        let pairs = match self.pairs.len() {
+            1 => "1 pair".to_string(),
+            n => format!("{n} pairs"),
+        };
+        PredefinedMenuItem::about(
+            Some("About"),
+            Some(AboutMetadata {
+                name: Some(DEFAULT_TOOLTIP.to_string()),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                comments: Some(format!("Monitoring {pairs} on {}", exchanges.join(", "))),
+                ..AboutMetadata::default()
+            }),
+        )
+    }
+
+    /// How often the tray loop wakes to take new prices and check the
+    /// timeouts: as often as it may redraw, within [`MIN_TICK`] and
+    /// [`MAX_TICK`]. Menu and icon events wake it right away.
+    fn tick(&self) -> Duration {
+        self.min_redraw.clamp(MIN_TICK, MAX_TICK)
+    }
+
+    /// Rotation only makes sense with more than one pair, and none fixed to
+    /// the title.
+    fn rotation_period(&self) -> Option<Duration> {
+        self.title_rotation
+            .filter(|_| self.pairs.len() > 1 && self.main_pair().is_none())
+    }
+
+    /// The pair the main icon is fixed to: the `primary_pair`, or the first
+    /// with `icon_per_pair`.
+    fn main_pair(&self) -> Option<&(Exchange, String)> {
+        self.primary_pair
+            .as_ref()
+            .or_else(|| self.pairs.first().filter(|_| self.icon_per_pair))
+    }
+
+    /// Add and remove pair icons to match the pairs after the first, up to
+    /// [`MAX_PAIR_ICONS`] icons in all. Trays that take fewer refuse the
+    /// rest, which is logged.
+    fn sync_pair_icons(
+        &self,
+        pair_icons: &mut HashMap<(Exchange, String), PairIcon>,
+        icons: &DirectionIcons,
+    ) {
+        let wanted: Vec<&(Exchange, String)> = self
+            .pairs
+            .iter()
+            .take(MAX_PAIR_ICONS)
+            .skip(1)
+            .filter(|_| self.icon_per_pair)
+            .collect();
+        pair_icons.retain(|key, _| wanted.contains(&key));
+        for key in wanted {
+            if pair_icons.contains_key(key) {
+                continue;
+            }
+            let label = self.format_pair(&key.0, &key.1);
+            match TrayIconBuilder::new()
+                .with_id(format!("{} {}", key.0, key.1))
+                .with_title(&label)
+                .with_tooltip(&label)
+                .with_icon(icons.get(IconKind::Disconnected))
+                .build()
+            {
+                Ok(tray) => {
+                    let icon = PairIcon {
+                        tray,
+                        shown_icon: Some(IconKind::Disconnected),
+                    };
+                    pair_icons.insert(key.clone(), icon);
+                }
+                Err(err) => {
+                    tracing::warn!("no tray icon for {label}: {err}");
+                    break;
+                }
+            }
+        }
+        if self.icon_per_pair && self.pairs.len() > MAX_PAIR_ICONS {
+            tracing::warn!(
+                "only the first {MAX_PAIR_ICONS} pairs get a tray icon, the rest are in the menu"
+            );
+        }
+    }
+
+    /// The pair after `current`, in config order, that has a price to show.
+    fn next_title_pair(
+        &self,
+        current: Option<&(Exchange, String)>,
+        latest: &HashMap<(Exchange, String), (PriceUpdate, Direction)>,
+    ) -> Option<(Exchange, String)> {
+        let start = current
+            .and_then(|current| self.pairs.iter().position(|pair| pair == current))
+            .map_or(0, |i| i + 1);
+        (0..self.pairs.len())
+            .map(|i| &self.pairs[(start + i) % self.pairs.len()])
+            .find(|pair| latest.contains_key(*pair))
+            .cloned()
+    }
+
+    /// Format the price with the pair's precision, widened to keep a few
+    /// significant figures of tiny prices.
+    pub fn format_price(&self, update: &PriceUpdate) -> String {
+        self.format_amount(update, update.price)
+    }
+
+    fn format_amount(&self, update: &PriceUpdate, amount: Decimal) -> String {
+        let amount = match self.decimals.get(&(update.exchange, update.pair.clone())) {
+            Some(&decimals) => {
+                let decimals = number_format::decimals_for(amount, decimals);
+                let amount = self.rounding_mode.round(amount, decimals);
+                format!("{:.*}", decimals as usize, amount)
+            }
+            None => amount.to_string(),
+        };
+        self.number_locale.format(&amount)
+    }
+
+    /// The `stale_marker` if the update's pair stopped updating.
+    fn stale_suffix(&self, update: &PriceUpdate) -> Option<&str> {
+        Some(self.stale_marker.as_str()).filter(|marker| {
+            !marker.is_empty() && self.stale.contains(&(update.exchange, update.pair.clone()))
+        })
+    }
+
+    /// The pairs last heard from longer than `stale_after` ago. Repeated
+    /// prices aren't forwarded, so a flat pair is only stale once its health
+    /// has no recent price either; `heard` covers restored prices, which the
+    /// health knows nothing about, and the time since resuming.
+    fn find_stale(&self, health: &Health) -> HashSet<(Exchange, String)> {
+        let health = health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let stale_after_ms = self.stale_after.as_millis() as i64;
+        self.heard
+            .iter()
+            .filter(|(key, heard_ms)| {
+                let received = health.get(*key).and_then(|health| health.last_update);
+                now_ms - **heard_ms > stale_after_ms
+                    && received.is_none_or(|at| at.elapsed() > self.stale_after)
+            })
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// `MA(20): 64,900.00`, once the pair has a full moving average window.
+    fn format_moving_average(&self, update: &PriceUpdate) -> Option<String> {
+        let average = self.history.moving_average(update.exchange, &update.pair)?;
+        Some(format!(
+            "MA({}): {}",
+            average.window,
+            self.format_amount(update, average.value)
+        ))
+    }
+
+    /// The consolidated price of the update's pair, when aggregating and the
+    /// pair has prices from more than one exchange.
+    fn aggregate(&self, update: &PriceUpdate) -> Option<AggregatedPrice> {
+        self.aggregator.as_ref()?.get(&update.pair)
+    }
+
+    /// The price shown in the title or icon: the consolidated one if there
+    /// is one, in the display currency if set. Returns whether it was
+    /// converted.
+    fn shown_price(&self, update: &PriceUpdate) -> (String, bool) {
+        let (amount, converted) = self.shown_amount(update);
+        (self.format_amount(update, amount), converted)
+    }
+
+    /// [`TrayUI::shown_price`] before formatting.
+    fn shown_amount(&self, update: &PriceUpdate) -> (Decimal, bool) {
+        let price = self
+            .aggregate(update)
+            .map_or(update.price, |aggregate| aggregate.price);
+        match self
+            .fx
+            .as_ref()
+            .and_then(|fx| fx.convert(&update.pair, price))
+        {
+            // Keep the precision the exchange quoted in, not the rate's.
+            Some(converted) => (converted.round_dp(price.scale()), true),
+            None => (price, false),
+        }
+    }
+
+    fn format_shown_price(&self, update: &PriceUpdate) -> String {
+        self.shown_price(update).0
+    }
+
+    /// Format the tray title for an update, e.g. `BTC-USDT: 65000` or,
+    /// with `show_exchange`, `OKX BTC-USDT: 65000`. A consolidated price has
+    /// no exchange to show. The compact styles leave out the quote and the
+    /// exchange: `BTC 65k` and `BTC`. With `title_value = "change"` the
+    /// change replaces the price: `BTC-USDT: +2.3%`.
+    pub fn format_title(&self, update: &PriceUpdate) -> String {
+        let base = update.pair.split('-').next().unwrap_or_default();
+        let change = self
+            .change(update)
+            .filter(|_| self.title_value == TitleValue::Change)
+            .map(|(pct, _)| format!("{pct:+.1}%"));
+        let (price, converted) = match (self.title_style, change) {
+            (TitleStyle::SymbolOnly, _) => return base.to_string(),
+            (_, Some(change)) => (change, false),
+            (TitleStyle::Compact, None) => {
+                let (amount, converted) = self.shown_amount(update);
+                let price = match number_format::abbreviate(amount) {
+                    Some((amount, suffix)) => {
+                        format!("{}{suffix}", self.number_locale.format(&amount.to_string()))
+                    }
+                    None => self.format_amount(update, amount),
+                };
+                (price, converted)
+            }
+            (TitleStyle::Full, None) => self.shown_price(update),
+        };
+        let price = match (converted, &self.fx) {
+            (true, Some(fx)) => format!("{}{price}", fx.symbol()),
+            _ => price,
+        };
+        if self.title_style == TitleStyle::Compact {
+            format!("{base} {price}")
+        } else if self.show_exchange && self.aggregate(update).is_none() {
+            format!("{} {}: {}", update.exchange, update.pair, price)
+        } else {
+            format!("{}: {}", update.pair, price)
+        }
+    }
+
+    fn format_pair(&self, exchange: &Exchange, pair: &str) -> String {
+        if self.show_exchange {
+            format!("{exchange} {pair}")
+        } else {
+            pair.to_string()
+        }
+    }
+
+    /// Menu label such as `BTC-USDT  65000  12:03:45`, with the time the price
+    /// was received in local time.
+    fn format_menu_label(&self, update: &PriceUpdate) -> String {
+        format!(
+            "{}  {}  {}",
+            self.format_pair(&update.exchange, &update.pair),
+            self.format_price(update),
+            Local
+                .timestamp_millis_opt(update.timestamp_ms)
+                .single()
+                .map(|time| time.format("%H:%M:%S").to_string())
+                .unwrap_or_default()
+        )
+    }
+
+    /// Menu label for a pair that has not received a price yet.
+    fn format_empty_label(&self, exchange: &Exchange, pair: &str) -> String {
+        format!("{}  —", self.format_pair(exchange, pair))
+    }
+
+    /// Tooltip line such as `OKX BTC-USDT: 65000 (+2.3%)`, or `(+0.4% since
+    /// start)` and `(+12.0% vs 58000)` for the other baselines. The change
+    /// is left out when there is nothing to measure it against.
+    fn format_tooltip(&self, update: &PriceUpdate) -> String {
+        let mut tooltip = format!(
+            "{} {}: {}",
+            update.exchange,
+            update.pair,
+            self.format_price(update)
+        );
+        match self.change(update) {
+            Some((pct, Some(against))) => tooltip.push_str(&format!(" ({pct:+.1}% {against})")),
+            Some((pct, None)) => tooltip.push_str(&format!(" ({pct:+.1}%)")),
+            None => {}
+        }
+        tooltip
+    }
+
+    /// The change in percent against the `change_baseline`, with what it was
+    /// measured against unless that is the 24h change: `since start` or
+    /// `vs 58000`.
+    fn change(&self, update: &PriceUpdate) -> Option<(Decimal, Option<String>)> {
+        let key = (update.exchange, update.pair.clone());
+        let reference = self
+            .reference_prices
+            .get(&key)
+            .filter(|_| self.change_baseline == ChangeBaseline::CustomPrice);
+        match (self.change_baseline, reference) {
+            (ChangeBaseline::Session, _) => {
+                let pct = change_pct(*self.session_open.get(&key)?, update.price)?;
+                Some((pct, Some("since start".to_string())))
+            }
+            (_, Some(&reference)) => {
+                let pct = change_pct(reference, update.price)?;
+                Some((
+                    pct,
+                    Some(format!("vs {}", self.format_amount(update, reference))),
+                ))
+            }
+            _ => Some((update.change_pct_24h?, None)),
+        }
+    }
+
+    /// Tooltip line such as `BTC-USDT: 65000 volume-weighted across 3
+    /// exchanges`.
+    fn format_aggregate(&self, update: &PriceUpdate) -> Option<String> {
+        let aggregate = self.aggregate(update)?;
+        Some(format!(
+            "{}: {} volume-weighted across {} exchanges",
+            aggregate.pair,
+            self.format_amount(update, aggregate.price),
+            aggregate.exchanges.len()
+        ))
+    }
+
+    /// Tooltip line such as `bid 64999 / ask 65001 (0.003%)`, if the exchange
+    /// sent both sides of the book.
+    fn format_spread(&self, update: &PriceUpdate) -> Option<String> {
+        let (bid, ask) = (update.bid?, update.ask?);
+        Some(format!(
+            "bid {} / ask {} ({:.3}%)",
+            self.format_amount(update, bid),
+            self.format_amount(update, ask),
+            update.spread_pct()?
+        ))
+    }
+
+    /// Tooltip line such as `24h high 66000 / low 64000, volume 12,345 BTC`
+    /// with the figures the exchange's 24h ticker had.
+    fn format_stats(&self, update: &PriceUpdate) -> Option<String> {
+        let mut stats = Vec::new();
+        if let (Some(high), Some(low)) = (update.high_24h, update.low_24h) {
+            stats.push(format!(
+                "high {} / low {}",
+                self.format_amount(update, high),
+                self.format_amount(update, low)
+            ));
+        }
+        if let Some(volume) = update.volume_24h {
+            let base = update.pair.split('-').next().unwrap_or_default();
+            stats.push(format!(
+                "volume {} {base}",
+                self.number_locale.format(&volume.round().to_string())
+            ));
+        }
+        (!stats.is_empty()).then(|| format!("24h {}", stats.join(", ")))
+    }
+
+    /// Tooltip line such as `Funding rate 0.0100%` for perpetual swaps.
+    fn format_funding_rate(&self, update: &PriceUpdate) -> Option<String> {
+        let rate = update.funding_rate? * Decimal::ONE_HUNDRED;
+        Some(format!(
+            "Funding rate {}%",
+            self.number_locale.format(&format!("{rate:.4}"))
+        ))
+    }
+
+    /// Tooltip line such as `Balance 12,345.67 USD`, once it was fetched.
+    fn format_balance(&self) -> Option<String> {
+        let balance = self.balance.as_ref()?.latest()?;
+        Some(format!(
+            "Balance {} {}",
+            self.number_locale.format(&format!("{:.2}", balance.total)),
+            balance.currency
+        ))
+    }
+
+    /// Tooltip line such as `1m open 65010 / close 65000 (high 65020, low
+    /// 64990)` for pairs streamed as candles.
+    fn format_candle(&self, update: &PriceUpdate) -> Option<String> {
+        let candle = update.candle.as_ref()?;
+        Some(format!(
+            "1m open {} / close {} (high {}, low {})",
+            self.format_amount(update, candle.open),
+            self.format_amount(update, candle.close),
+            self.format_amount(update, candle.high),
+            self.format_amount(update, candle.low)
+        ))
+    }
+
+    /// Show the latest price in the title (or icon) and tooltip, or
+    /// "Disconnected" when every pair reported a connection problem or prices
+    /// stopped arriving. Pairs that are not connected are listed in the
+    /// tooltip, and a paused feed is marked in the title. Returns whether the
+    /// price was drawn into the icon.
+    fn refresh(
+        &mut self,
+        tray: &TrayIcon,
+        latest: Option<&(PriceUpdate, Direction)>,
+        statuses: &HashMap<(Exchange, String), ConnectionState>,
+        health: &Health,
+        timed_out: bool,
+        paused: bool,
+    ) -> bool {
+        let all_down = !statuses.is_empty()
+            && statuses
+                .values()
+                .all(|state| *state != ConnectionState::Connected);
+        let mut tooltip = Vec::new();
+        let mut rendered = false;
+        if let Some((update, direction)) = latest {
+            if !all_down && !timed_out {
+                let price = self.format_shown_price(update);
+                let old = self
+                    .restored
+                    .contains(&(update.exchange, update.pair.clone()));
+                let stale = self.stale_suffix(update).map(str::to_string);
+                let color = direction.text_color(self.theme.appearance());
+                match self.price_icons.as_mut() {
+                    Some(price_icons) => {
+                        let _ = tray.set_icon(Some(price_icons.get(&price, color)));
+                        tray.set_title(
+                            paused
+                                .then_some(PAUSED)
+                                .or(old.then_some(LAST_KNOWN))
+                                .or(stale.as_deref()),
+                        );
+                        rendered = true;
+                    }
+                    None => {
+                        let mut title =
+                            format!("{}{}", direction.arrow(), self.format_title(update));
+                        if old {
+                            title = format!("{title} {LAST_KNOWN}");
+                        }
+                        if let Some(stale) = stale {
+                            title = format!("{title} {stale}");
+                        }
+                        if paused {
+                            title = format!("{title} {PAUSED}");
+                        }
+                        tray.set_title(Some(title));
+                    }
+                }
+            }
+            tooltip.extend(self.format_aggregate(update));
+            tooltip.push(self.format_tooltip(update));
+            tooltip.extend(self.format_spread(update));
+            tooltip.extend(self.format_candle(update));
+            tooltip.extend(self.format_stats(update));
+            tooltip.extend(self.format_funding_rate(update));
+            tooltip.extend(self.history.sparkline(update.exchange, &update.pair));
+            tooltip.extend(self.format_moving_average(update));
+        }
+        let mut invalid: Vec<&str> = statuses
+            .iter()
+            .filter(|(_, state)| **state == ConnectionState::InvalidSymbol)
+            .map(|((_, pair), _)| pair.as_str())
+            .collect();
+        invalid.sort();
+        if all_down && !invalid.is_empty() {
+            tray.set_title(Some(format!("Unknown pair: {}", invalid.join(", "))));
+        } else if all_down || timed_out {
+            tray.set_title(Some("Disconnected"));
+        } else if paused && latest.is_none() {
+            tray.set_title(Some(PAUSED));
+        }
+        let health = health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut problems: Vec<String> = statuses
+            .iter()
+            .filter(|(_, state)| **state != ConnectionState::Connected)
+            .map(|(key, state)| {
+                let mut line = format!("{} {}: {state}", key.0, key.1);
+                match health
+                    .get(key)
+                    .map_or(0, |health| health.consecutive_errors)
+                {
+                    0 => {}
+                    1 => line.push_str(" (1 failed attempt)"),
+                    n => line.push_str(&format!(" ({n} failed attempts)")),
+                }
+                line
+            })
+            .collect();
+        problems.sort();
+        tooltip.extend(problems);
+        tooltip.extend(self.format_balance());
+        if tooltip.is_empty() {
+            tooltip.push(DEFAULT_TOOLTIP.to_string());
+        }
+        let _ = tray.set_tooltip(Some(tooltip.join("\n")));
+        rendered
+    }
+
+    /// Show the latest price of a pair on its own icon, or its connection
+    /// problem if it has one.
+    fn refresh_pair_icon(
+        &mut self,
+        icon: &mut PairIcon,
+        key: &(Exchange, String),
+        latest: Option<&(PriceUpdate, Direction)>,
+        state: Option<&ConnectionState>,
+        icons: &DirectionIcons,
+        timed_out: bool,
+    ) {
+        let problem = match state {
+            Some(state) if *state != ConnectionState::Connected => Some(state.to_string()),
+            _ => timed_out.then(|| "Disconnected".to_string()),
+        };
+        let kind = match (latest, problem) {
+            (Some((update, direction)), None) => {
+                let _ = icon.tray.set_tooltip(Some(self.format_tooltip(update)));
+                let price = self.format_shown_price(update);
+                let color = direction.text_color(self.theme.appearance());
+                let stale = self.stale_suffix(update).map(str::to_string);
+                if let Some(price_icons) = self.price_icons.as_mut() {
+                    let _ = icon.tray.set_icon(Some(price_icons.get(&price, color)));
+                    icon.tray.set_title(stale);
+                    icon.shown_icon = None;
+                    return;
+                }
+                let mut title = format!("{}{}", direction.arrow(), self.format_title(update));
+                if let Some(stale) = stale {
+                    title = format!("{title} {stale}");
+                }
+                icon.tray.set_title(Some(title));
+                IconKind::Direction(*direction)
+            }
+            (_, problem) => {
+                // Also before the pair's first status, while it connects.
+                let kind = match (state, &problem) {
+                    (Some(_), None) => IconKind::Direction(Direction::Neutral),
+                    _ => IconKind::Disconnected,
+                };
+                let label = self.format_pair(&key.0, &key.1);
+                let title = match problem {
+                    Some(problem) => format!("{label}: {problem}"),
+                    None => label,
+                };
+                let _ = icon.tray.set_tooltip(Some(&title));
+                icon.tray.set_title(Some(title));
+                kind
+            }
+        };
+        if icon.shown_icon != Some(kind) {
+            let _ = icon.tray.set_icon(Some(icons.get(kind)));
+            icon.shown_icon = Some(kind);
+        }
+    }
+
+    /// Stand-in for [`TrayUI::run`] where there is no tray: print the title
+    /// for each price to stdout and log connection changes, until the price
+    /// feed ends or anything is sent on `quit`. Alerts still fire.
+    fn run_without_tray(
+        mut self,
+        rx: Receiver<PriceUpdate>,
+        status_rx: Receiver<ConnectionEvent>,
+        monitoring: MonitoringHandle,
+        sinks: Vec<JoinHandle<()>>,
+        quit: Receiver<()>,
+    ) -> ! {
+        let mut latest: HashMap<(Exchange, String), PriceUpdate> = HashMap::new();
+        while quit.try_recv().is_err() {
+            for event in status_rx.try_iter() {
+                tracing::info!("{} {}: {}", event.exchange, event.pair, event.state);
+            }
+            match rx.recv_timeout(QUIT_POLL) {
+                Ok(update) => {
+                    for message in self.alerts.check(&update) {
+                        alerts::notify(&message);
+                    }
+                    println!("{}", self.format_title(&update));
+                    latest.insert((update.exchange, update.pair.clone()), update);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.snapshot_last_prices(latest.values());
+        shut_down(monitoring, sinks);
+        std::process::exit(0)
+    }
+
+    /// Run the tray event loop on the current thread. Never returns; when the
+    /// user quits, or anything is sent on `quit`, `monitoring` is shut down
+    /// and the `sinks` are waited for before the process exits. Configs received on `reloads` or saved from
+    /// the settings menu are applied to both the monitored pairs and the
+    /// display.
+    pub fn run(
+        mut self,
+        rx: Receiver<PriceUpdate>,
+        status_rx: Receiver<ConnectionEvent>,
+        monitoring: MonitoringHandle,
+        sinks: Vec<JoinHandle<()>>,
+        mut reloads: Option<Receiver<Config>>,
+        quit: Receiver<()>,
+    ) -> ! {
+        let icons = DirectionIcons::load(
+            &self.icon_path,
+            self.icon_path_disconnected.as_deref(),
+            self.icon_path_alert.as_deref(),
+        );
+
+        let Some(event_loop) = new_event_loop() else {
+            tracing::error!("no display to show a tray on; printing prices instead");
+            self.run_without_tray(rx, status_rx, monitoring, sinks, quit);
+        };
+
+        let tray_menu = Menu::new();
+        let mut pair_menu = PairMenu::new(tray_menu.clone());
+
+        let copy_i = MenuItem::new("Copy current price", false, None);
+        let pause_i = MenuItem::new("Pause", true, None);
+        let mut about_i = self.about_item();
+        let quit_i = MenuItem::new("Quit", true, None);
+        tray_menu
+            .append_items(&[
+                &copy_i,
+                &pair_menu.open,
+                &pause_i,
+                self.settings.submenu(),
+                &PredefinedMenuItem::separator(),
+                &about_i,
+                &quit_i,
+            ])
+            .expect("Failed to build tray menu");
+
+        pair_menu.set_pairs(&self.pairs, |exchange, pair| {
+            self.format_empty_label(exchange, pair)
+        });
+
+        let tray = TrayIconBuilder::new()
+            .with_id("1")
+            .with_menu(Box::new(tray_menu.clone()))
+            .with_title(CONNECTING)
+            .with_tooltip(DEFAULT_TOOLTIP)
+            .with_menu_on_left_click(self.left_click == ClickAction::None)
+            .with_icon(icons.get(IconKind::Disconnected))
+            .build();
+        let mut tray_icon = match tray {
+            Ok(tray) => Some(tray),
+            Err(err) => {
+                tracing::error!("the system tray is unavailable ({err}); printing prices instead");
+                self.run_without_tray(rx, status_rx, monitoring, sinks, quit);
+            }
+        };
+
+        let mut pair_icons: HashMap<(Exchange, String), PairIcon> = HashMap::new();
+        self.sync_pair_icons(&mut pair_icons, &icons);
+
+        let menu_channel = MenuEvent::receiver();
+        let tray_channel = TrayIconEvent::receiver();
+        // `None` while the icon shows a rendered price.
+        let mut shown_icon = Some(IconKind::Disconnected);
+        let mut latest: HashMap<(Exchange, String), (PriceUpdate, Direction)> = HashMap::new();
+        for update in self.restore_last_prices() {
+            pair_menu.set_label(
+                update.exchange,
+                &update.pair,
+                self.format_menu_label(&update),
+            );
+            latest.insert(
+                (update.exchange, update.pair.clone()),
+                (update, Direction::Neutral),
+            );
+        }
+        let mut title_pair = self
+            .main_pair()
+            .cloned()
+            .or_else(|| self.next_title_pair(None, &latest));
+        // Set when the title pair was picked by clicking the icon.
+        let mut pinned = false;
+        let mut rotated_at = Instant::now();
+        let mut statuses: HashMap<(Exchange, String), ConnectionState> = HashMap::new();
+        let mut last_price_at = Instant::now();
+        let mut timed_out = false;
+        let mut redraw_pending = !latest.is_empty();
+        let mut redrawn_at = Instant::now();
+        let mut flash_until: Option<Instant> = None;
+        let mut alert_until: Option<Instant> = None;
+        // Pairs whose last move flashed; they flash again only after a
+        // smaller move, so a run of large moves doesn't keep flashing.
+        let mut flashed: HashSet<(Exchange, String)> = HashSet::new();
+        let mut clipboard = None;
+        let health = monitoring.health().clone();
+        let mut monitoring = Some(monitoring);
+        let mut sinks = Some(sinks);
+        event_loop.run(move |event, _, control_flow| {
+            *control_flow = ControlFlow::WaitUntil(Instant::now() + self.tick());
+
+            if let Event::LoopDestroyed = event {
+                self.snapshot_last_prices(latest.values().map(|(update, _)| update));
+                if let (Some(monitoring), Some(sinks)) = (monitoring.take(), sinks.take()) {
+                    shut_down(monitoring, sinks);
+                }
+                return;
+            }
+
+            let mut changed = false;
+            let mut edited = None;
+
+            if let Ok(event) = menu_channel.try_recv() {
+                if event.id == copy_i.id() {
+                    if let Some((update, _)) = title_pair.as_ref().and_then(|key| latest.get(key)) {
+                        copy_to_clipboard(&mut clipboard, &self.format_shown_price(update));
+                    }
+                }
+                if let Some((exchange, pair)) = pair_menu.link(&event.id) {
+                    let url = exchange.trade_url(pair);
+                    if let Err(err) = open::that_detached(&url) {
+                        tracing::warn!("failed to open {url}: {err}");
+                    }
+                }
+                if event.id == pause_i.id() {
+                    if let Some(monitoring) = monitoring.as_mut() {
+                        if monitoring.is_paused() {
+                            monitoring.resume();
+                            pause_i.set_text("Pause");
+                            last_price_at = Instant::now();
+                            // Give the pairs until `stale_after` to update again.
+                            let now_ms = chrono::Utc::now().timestamp_millis();
+                            self.heard
+                                .values_mut()
+                                .for_each(|heard_ms| *heard_ms = now_ms);
+                        } else {
+                            futures::executor::block_on(monitoring.pause());
+                            pause_i.set_text("Resume");
+                            statuses.clear();
+                        }
+                        changed = true;
+                    }
+                }
+                if self.settings.is_edit_file(&event.id) {
+                    self.edit_config(&mut reloads);
+                }
+                if let Some(change) = self.settings.change_for(&event.id) {
+                    match settings::save(&change, &self.config_path) {
+                        Ok(config) => edited = Some(config),
+                        Err(err) => tracing::error!("failed to save settings: {err}"),
+                    }
+                }
+                if event.id == quit_i.id() {
+                    tray_icon.take();
+                    pair_icons.clear();
+
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+
+            // Interrupted: leave the way Quit does, so the icon doesn't linger.
+            if quit.try_recv().is_ok() {
+                tray_icon.take();
+                pair_icons.clear();
+                *control_flow = ControlFlow::Exit;
+            }
+
+            if let Ok(event) = tray_channel.try_recv() {
+                // A pair icon always stands for its own pair.
+                let clicked = pair_icons
+                    .iter()
+                    .find(|(_, icon)| icon.tray.id() == &event.id)
+                    .map(|(key, _)| key.clone());
+                let action = match event.click_type {
+                    ClickType::Left => self.left_click,
+                    ClickType::Double => self.double_click,
+                    ClickType::Right => ClickAction::None,
+                };
+                match action {
+                    ClickAction::None => {}
+                    ClickAction::NextPair if clicked.is_some() || self.main_pair().is_some() => {}
+                    ClickAction::NextPair => {
+                        title_pair = self.next_title_pair(title_pair.as_ref(), &latest);
+                        rotated_at = Instant::now();
+                        pinned = title_pair.is_some();
+                        changed = true;
+                    }
+                    ClickAction::OpenExchange => {
+                        if let Some((exchange, pair)) = clicked.as_ref().or(title_pair.as_ref()) {
+                            let url = exchange.trade_url(pair);
+                            if let Err(err) = open::that_detached(&url) {
+                                tracing::warn!("failed to open {url}: {err}");
+                            }
+                        }
+                    }
+                    ClickAction::EditConfig => self.edit_config(&mut reloads),
+                }
+            }
+
+            let reloaded = edited.or_else(|| reloads.as_ref().and_then(|r| r.try_recv().ok()));
+            if let Some(config) = reloaded {
+                if let Some(monitoring) = monitoring.as_mut() {
+                    if let Err(err) = futures::executor::block_on(monitoring.reload(&config)) {
+                        tracing::error!("failed to apply reloaded config: {err}");
+                    }
+                }
+                self.reconfigure(&config);
+                if let Some(ref tray) = tray_icon {
+                    tray.set_show_menu_on_left_click(self.left_click == ClickAction::None);
+                }
+                // Pairs that are no longer monitored send no more events.
+                statuses.retain(|key, _| self.pairs.contains(key));
+                latest.retain(|key, _| self.pairs.contains(key));
+                flashed.retain(|key| self.pairs.contains(key));
+                if title_pair
+                    .as_ref()
+                    .is_some_and(|key| !latest.contains_key(key))
+                {
+                    title_pair = self.next_title_pair(None, &latest);
+                    pinned = false;
+                }
+                if let Some(main) = self.main_pair() {
+                    title_pair = Some(main.clone());
+                }
+                self.sync_pair_icons(&mut pair_icons, &icons);
+                changed = true;
+                pair_menu.set_pairs(&self.pairs, |exchange, pair| {
+                    self.format_empty_label(exchange, pair)
+                });
+                // The dialog's text is fixed once built, so it is replaced.
+                let _ = tray_menu.remove(&about_i);
+                about_i = self.about_item();
+                let position = tray_menu.items().len().saturating_sub(1);
+                if let Err(err) = tray_menu.insert(&about_i, position) {
+                    tracing::warn!("failed to update the about item: {err}");
+                }
+            }
+
+            if self.balance.as_mut().is_some_and(Balance::take_changed) {
+                changed = true;
+            }
+
+            // Redraw a rendered price in the colours of the new appearance.
+            if self.theme.take_changed() && self.price_icons.is_some() {
+                changed = true;
+            }
+
+            for event in status_rx.try_iter() {
+                tracing::info!("{} {}: {}", event.exchange, event.pair, event.state);
+                if matches!(
+                    event.state,
+                    ConnectionState::Stopped | ConnectionState::InvalidSymbol
+                ) {
+                    let label = format!(
+                        "{}  {}",
+                        self.format_pair(&event.exchange, &event.pair),
+                        event.state
+                    );
+                    pair_menu.set_label(event.exchange, &event.pair, label);
+                    if let Some(aggregator) = self.aggregator.as_mut() {
+                        aggregator.remove(event.exchange, &event.pair);
+                    }
+                }
+                statuses.insert((event.exchange, event.pair), event.state);
+                changed = true;
+            }
+
+            // Take everything queued since the last iteration but only show the
+            // newest price of each pair. Alerts still see every price.
+            let mut updates: Vec<PriceUpdate> = Vec::new();
+            for update in rx.try_iter().take(MAX_UPDATES_PER_TICK) {
+                for message in self.alerts.check(&update) {
+                    alerts::notify(&message);
+                    if icons.alert.is_some() {
+                        alert_until = Some(Instant::now() + ALERT_ICON);
+                        changed = true;
+                    }
+                }
+                updates.retain(|queued| {
+                    queued.exchange != update.exchange || queued.pair != update.pair
+                });
+                updates.push(update);
+            }
+            for update in updates {
+                let key = (update.exchange, update.pair.clone());
+                // The feed is alive even if the price is not worth showing.
+                last_price_at = Instant::now();
+                self.heard.insert(key.clone(), update.timestamp_ms);
+                self.session_open.entry(key.clone()).or_insert(update.price);
+                // A restored price is replaced however little it moved, and
+                // isn't what the direction is measured from.
+                let restored = self.restored.remove(&key);
+                if !restored
+                    && latest
+                        .get(&key)
+                        .is_some_and(|(shown, _)| self.is_negligible(shown, &update))
+                {
+                    continue;
+                }
+                let previous = latest
+                    .get(&key)
+                    .filter(|_| !restored)
+                    .map(|(previous, _)| previous.price);
+                let direction = Direction::between(previous, update.price);
+                if previous.is_some_and(|previous| self.is_flash_move(previous, update.price)) {
+                    if flashed.insert(key.clone()) {
+                        flash_until = Some(Instant::now() + FLASH);
+                        changed = true;
+                    }
+                } else {
+                    flashed.remove(&key);
+                }
+                pair_menu.set_label(
+                    update.exchange,
+                    &update.pair,
+                    self.format_menu_label(&update),
+                );
+                // While rotating, the title moves on by itself once it shows a
+                // pair, and a clicked pair stays until the next click.
+                if let Some(main) = self.main_pair() {
+                    title_pair = Some(main.clone());
+                } else if (self.rotation_period().is_none() && !pinned) || title_pair.is_none() {
+                    title_pair = Some(key.clone());
+                    rotated_at = Instant::now();
+                }
+                if let Some(aggregator) = self.aggregator.as_mut() {
+                    aggregator.push(&update);
+                }
+                self.history.push(update.clone());
+                latest.insert(key, (update, direction));
+                redraw_pending = true;
+            }
+
+            if let Some(period) = self.rotation_period() {
+                if rotated_at.elapsed() >= period {
+                    rotated_at = Instant::now();
+                    title_pair = self.next_title_pair(title_pair.as_ref(), &latest);
+                    redraw_pending = true;
+                }
+            }
+
+            let paused = monitoring.as_ref().is_some_and(|m| m.is_paused());
+            // Repeated prices aren't forwarded but still show the feed is alive.
+            let stale = !paused
+                && last_price_at.elapsed() > self.stale_after
+                && !exchange::heard_within(&health, self.stale_after);
+            if stale != timed_out {
+                timed_out = stale;
+                changed = true;
+            }
+
+            // Prices age while paused, but aren't marked as stale then.
+            let stale_pairs = if paused {
+                HashSet::new()
+            } else {
+                self.find_stale(&health)
+            };
+            if stale_pairs != self.stale {
+                self.stale = stale_pairs;
+                changed = true;
+            }
+
+            let flashing = flash_until.is_some_and(|until| Instant::now() < until);
+            if !flashing && flash_until.take().is_some() {
+                changed = true;
+            }
+            let alerting = alert_until.is_some_and(|until| Instant::now() < until);
+            if !alerting && alert_until.take().is_some() {
+                changed = true;
+            }
+            // From startup until a pair connects, and whenever the feed is down.
+            let disconnected = !paused
+                && (timed_out
+                    || !statuses
+                        .values()
+                        .any(|state| *state == ConnectionState::Connected));
+
+            // New prices are shown at most every `min_redraw`; anything else,
+            // such as the feed timing out, right away.
+            if changed || (redraw_pending && redrawn_at.elapsed() >= self.min_redraw) {
+                redraw_pending = false;
+                redrawn_at = Instant::now();
+                if let Some(ref tray) = tray_icon {
+                    let shown = title_pair.as_ref().and_then(|key| latest.get(key));
+                    copy_i.set_enabled(shown.is_some());
+                    if self.refresh(tray, shown, &statuses, &health, timed_out, paused) {
+                        shown_icon = None;
+                    } else {
+                        let direction =
+                            shown.map_or(Direction::Neutral, |(_, direction)| *direction);
+                        let kind = if alerting {
+                            IconKind::Alert
+                        } else if disconnected {
+                            IconKind::Disconnected
+                        } else {
+                            IconKind::Direction(direction)
+                        };
+                        if shown_icon != Some(kind) {
+                            let _ = tray.set_icon(Some(icons.get(kind)));
+                            shown_icon = Some(kind);
+                        }
+                    }
+                    if flashing {
+                        let _ = tray.set_icon(Some(icons.flash.clone()));
+                        // Put the regular icon back once the flash is over.
+                        shown_icon = None;
+                    }
+                }
+                for (key, icon) in pair_icons.iter_mut() {
+                    self.refresh_pair_icon(
+                        icon,
+                        key,
+                        latest.get(key),
+                        statuses.get(key),
+                        &icons,
+                        timed_out,
+                    );
+                }
+            }
+
+            // Sleep until the next tick, or sooner if a flash, the alert icon
+            // or the title is due to change.
+            if *control_flow != ControlFlow::Exit {
+                let deadlines = [
+                    flash_until,
+                    alert_until,
+                    self.rotation_period().map(|period| rotated_at + period),
+                    redraw_pending.then(|| redrawn_at + self.min_redraw),
+                ];
+                let wake = deadlines
+                    .into_iter()
+                    .flatten()
+                    .fold(Instant::now() + self.tick(), Instant::min);
+                *control_flow = ControlFlow::WaitUntil(wake);
+            }
+        })
+    }
+}
+
+/// Stop monitoring, then wait for the `sinks` to finish with what the feed
+/// sent last, as the process exits right after.
+fn shut_down(monitoring: MonitoringHandle, sinks: Vec<JoinHandle<()>>) {
+    futures::executor::block_on(async {
+        monitoring.shutdown().await;
+        futures::future::join_all(sinks).await;
+    });
+}
+
+/// The tray's event loop, or `None` where there is no display for it, such
+/// as on a headless Linux box, where tao panics.
+fn new_event_loop() -> Option<EventLoop<()>> {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let event_loop = std::panic::catch_unwind(|| EventLoopBuilder::new().build()).ok();
+    std::panic::set_hook(hook);
+    event_loop
+}
+
+/// Put `text` on the system clipboard. The clipboard is opened once and kept,
+/// as on X11 its contents are only served while it is open.
+fn copy_to_clipboard(clipboard: &mut Option<arboard::Clipboard>, text: &str) {
+    if clipboard.is_none() {
+        match arboard::Clipboard::new() {
+            Ok(opened) => *clipboard = Some(opened),
+            Err(err) => {
+                tracing::warn!("failed to open the clipboard: {err}");
+                return;
+            }
+        }
+    }
+    if let Some(clipboard) = clipboard {
+        if let Err(err) = clipboard.set_text(text) {
+            tracing::warn!("failed to copy to the clipboard: {err}");
+        }
+    }
+}
+
+/// The monitored pairs in config order, except that the `primary_pair`
+/// comes first, as the main icon shows the first pair.
+fn configured_pairs(config: &Config) -> Vec<(Exchange, String)> {
+    let mut pairs = Vec::new();
+    for pair_config in &config.trading_pairs {
+        if let Ok(key) = exchange::parse_pair(&pair_config.pair) {
+            if !pairs.contains(&key) {
+                pairs.push(key);
+            }
+        }
+    }
+    if let Some(primary) = primary_pair(config) {
+        if let Some(index) = pairs.iter().position(|key| *key == primary) {
+            let primary = pairs.remove(index);
+            pairs.insert(0, primary);
+        }
+    }
+    pairs
+}
+
+fn primary_pair(config: &Config) -> Option<(Exchange, String)> {
+    config
+        .primary_pair
+        .as_ref()
+        .and_then(|pair| exchange::parse_pair(pair).ok())
+}
+
+/// The pair's own `decimals`, or else the global ones capped at the tick
+/// size's. Without either, the tick size's or [`DEFAULT_DECIMALS`].
+fn pair_decimals(
+    config: &Config,
+    tick_decimals: &HashMap<(Exchange, String), u32>,
+) -> HashMap<(Exchange, String), u32> {
+    config
+        .trading_pairs
+        .iter()
+        .filter_map(|pair_config| {
+            let key = exchange::parse_pair(&pair_config.pair).ok()?;
+            let tick = tick_decimals.get(&key).copied();
+            let decimals = match (pair_config.decimals, config.decimals, tick) {
+                (Some(decimals), _, _) => decimals,
+                (None, Some(decimals), Some(tick)) => decimals.min(tick),
+                (None, decimals, tick) => decimals.or(tick).unwrap_or(DEFAULT_DECIMALS),
+            };
+            Some((key, decimals))
+        })
+        .collect()
+}
+
+fn pair_reference_prices(config: &Config) -> HashMap<(Exchange, String), Decimal> {
+    config
+        .trading_pairs
+        .iter()
+        .filter_map(|pair_config| {
+            let key = exchange::parse_pair(&pair_config.pair).ok()?;
+            Some((key, pair_config.reference_price?))
+        })
+        .collect()
+}
+
+/// The move from `from` to `to` in percent, `None` from zero.
+fn change_pct(from: Decimal, to: Decimal) -> Option<Decimal> {
+    (!from.is_zero()).then(|| (to - from) / from * Decimal::ONE_HUNDRED)
+}
+
+fn pair_min_change(config: &Config) -> HashMap<(Exchange, String), Decimal> {
+    config
+        .trading_pairs
+        .iter()
+        .filter_map(|pair_config| {
+            let key = exchange::parse_pair(&pair_config.pair).ok()?;
+            Some((key, config.min_change_for(pair_config)?))
+        })
+        .collect()
+}
+
+/// The icon `path` points to, PNG or ICO, or the built-in one if it can't be
+/// read so that a missing file doesn't keep the ticker from starting.
+fn load_rgba(path: &Path) -> (Vec<u8>, u32, u32) {
+    let image = open_image(path)
+        .unwrap_or_else(|err| {
+            if path.exists() {
+                tracing::warn!(
+                    "failed to load icon {}, using the built-in one: {err}",
+                    path.display()
+                );
+            } else {
+                tracing::warn!("{} not found, using the built-in icon", path.display());
+            }
+            image::load_from_memory(DEFAULT_ICON).expect("built-in icon is a valid PNG")
+        })
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    let rgba = image.into_raw();
+    (rgba, width, height)
+}
+
+/// The icon `path` points to, or `None` so that the main one is used if it
+/// can't be read.
+fn load_icon_variant(path: &Path) -> Option<tray_icon::Icon> {
+    let image = open_image(path)
+        .map_err(|err| {
+            tracing::warn!(
+                "failed to load icon {}, using the main one: {err}",
+                path.display()
+            )
+        })
+        .ok()?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    tray_icon::Icon::from_rgba(image.into_raw(), width, height)
+        .map_err(|err| tracing::warn!("invalid icon {}: {err}", path.display()))
+        .ok()
+}
+
+fn open_image(path: &Path) -> image::ImageResult<image::DynamicImage> {
+    image::io::Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(image::ImageError::from)
+        .and_then(|reader| reader.decode())
+}
+
+/// Blend every pixel halfway towards `color`, keeping its alpha.
+fn tint(rgba: &[u8], color: [u8; 3]) -> Vec<u8> {
+    rgba.chunks_exact(4)
+        .flat_map(|px| {
+            let blend = |i: usize| ((px[i] as u16 + color[i] as u16) / 2) as u8;
+            [blend(0), blend(1), blend(2), px[3]]
+        })
+        .collect()
+}